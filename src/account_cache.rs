@@ -0,0 +1,122 @@
+//! Account ID map and per-account snapshot cache
+//!
+//! `AccountDirectory` maps both `accountId` and `accountIdKey` to the account
+//! metadata from `/v1/accounts/list`, so exports accept either form.
+//!
+//! `AccountCache` keeps the last loaded `AccountSnapshot` per `accountIdKey`
+//! so repeated `get_accounts`/`get_account`/`get_aggregate_portfolio` calls
+//! do not re-fetch balances and positions for every account. `updated_at` of a snapshot is the time it was
+//! loaded. Only fully loaded accounts are cached, so partial failures are
+//! retried.
+
+use crate::error::PluginError;
+use crate::etrade::{AccountInfo, AccountSnapshot, ETradeClient};
+use crate::trace;
+use chrono::Utc;
+use std::collections::HashMap;
+
+/// Default maximum age of a cached account snapshot
+pub const DEFAULT_ACCOUNT_CACHE_TTL_MS: i64 = 30000;
+
+#[derive(Default)]
+pub struct AccountDirectory {
+    /// accountId and accountIdKey -> accountIdKey
+    keys: HashMap<String, String>,
+    /// accountIdKey -> metadata
+    infos: HashMap<String, AccountInfo>,
+}
+
+impl AccountDirectory {
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.infos.clear();
+    }
+
+    pub fn remember(&mut self, info: &AccountInfo) {
+        let key = &info.account_id_key;
+        self.keys.insert(info.account_id.clone(), key.clone());
+        self.keys.insert(key.clone(), key.clone());
+        self.infos.insert(key.clone(), info.clone());
+    }
+
+    /// Reload the map from the account list
+    pub fn refresh(&mut self, client: &ETradeClient) -> Result<Vec<AccountInfo>, PluginError> {
+        let infos = client.list_account_infos()?;
+        for info in &infos {
+            self.remember(info);
+        }
+        Ok(infos)
+    }
+
+    /// Resolve an `accountId` or `accountIdKey` to the `accountIdKey` used in
+    /// URL paths. Refreshes the map from the account list on a miss; unknown
+    /// IDs are passed through unchanged.
+    pub fn resolve(&mut self, client: &ETradeClient, account_id: &str) -> String {
+        if let Some(key) = self.keys.get(account_id) {
+            return key.clone();
+        }
+
+        if let Err(e) = self.refresh(client) {
+            trace::log_error("Failed to refresh account ID map", &e);
+        }
+
+        self.keys
+            .get(account_id)
+            .cloned()
+            .unwrap_or_else(|| account_id.to_string())
+    }
+
+    /// Metadata for a resolved `accountIdKey`
+    pub fn info(&self, account_id_key: &str) -> Option<&AccountInfo> {
+        self.infos.get(account_id_key)
+    }
+}
+
+pub struct AccountCache {
+    ttl_ms: i64,
+    entries: HashMap<String, AccountSnapshot>,
+}
+
+impl AccountCache {
+    pub fn new(ttl_ms: i64) -> Self {
+        Self {
+            ttl_ms,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn set_ttl(&mut self, ttl_ms: i64) {
+        self.ttl_ms = ttl_ms;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Return a snapshot no older than `max_age_ms` (or the cache TTL),
+    /// annotated with `from_cache` and `cache_age_ms` extensions
+    pub fn get(&self, account_id_key: &str, max_age_ms: Option<i64>) -> Option<AccountSnapshot> {
+        let cached = self.entries.get(account_id_key)?;
+        let age_ms = (Utc::now() - cached.summary.updated_at).num_milliseconds();
+        if age_ms >= max_age_ms.unwrap_or(self.ttl_ms) {
+            return None;
+        }
+
+        let mut snapshot = cached.clone();
+        let extensions = snapshot.summary.extensions.get_or_insert_with(HashMap::new);
+        extensions.insert("from_cache".to_string(), serde_json::Value::Bool(true));
+        extensions.insert("cache_age_ms".to_string(), serde_json::json!(age_ms));
+
+        Some(snapshot)
+    }
+
+    pub fn insert(&mut self, account_id_key: &str, snapshot: &AccountSnapshot) {
+        self.entries
+            .insert(account_id_key.to_string(), snapshot.clone());
+    }
+
+    /// Drop a snapshot that is known to be stale (e.g. after an order)
+    pub fn evict(&mut self, account_id_key: &str) {
+        self.entries.remove(account_id_key);
+    }
+}
@@ -0,0 +1,260 @@
+//! Household portfolio aggregation
+//!
+//! Merges positions from several accounts by symbol. Market values include
+//! the contract multiplier, and allocation is relative to gross exposure
+//! (sum of absolute market values) so long and short holdings both count.
+
+use crate::portfolio::{AssetClass, PositionDetail};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountContribution {
+    pub account_id: String,
+    pub quantity: f64,
+    pub average_price: f64,
+    pub market_value: f64,
+    /// Share of this symbol's gross market value held in the account
+    pub pct_of_symbol: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregatePosition {
+    pub symbol_id: String,
+    pub asset_class: AssetClass,
+    pub quantity: f64,
+    /// Quantity-weighted average cost per share/contract unit
+    pub average_price: f64,
+    pub current_price: f64,
+    pub cost_basis: f64,
+    pub market_value: f64,
+    pub unrealized_pnl: f64,
+    pub allocation_pct: f64,
+    pub accounts: Vec<AccountContribution>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregatePortfolio {
+    pub positions: Vec<AggregatePosition>,
+    pub total_market_value: f64,
+    pub gross_market_value: f64,
+    pub total_cost_basis: f64,
+    pub total_unrealized_pnl: f64,
+    pub account_count: usize,
+}
+
+/// Merge `(account_id, positions)` pairs into one portfolio
+pub fn aggregate_positions(accounts: &[(String, Vec<PositionDetail>)]) -> AggregatePortfolio {
+    let mut by_symbol: BTreeMap<String, Vec<(&str, &PositionDetail)>> = BTreeMap::new();
+    for (account_id, details) in accounts {
+        for detail in details {
+            by_symbol
+                .entry(detail.position.symbol_id.clone())
+                .or_default()
+                .push((account_id.as_str(), detail));
+        }
+    }
+
+    let mut positions: Vec<AggregatePosition> = by_symbol
+        .into_iter()
+        .map(|(symbol_id, holdings)| {
+            let first = holdings[0].1;
+            let mut quantity = 0.0;
+            let mut cost_basis = 0.0;
+            let mut market_value = 0.0;
+            let mut gross_value = 0.0;
+            let mut unrealized_pnl = 0.0;
+            let mut contributions = Vec::with_capacity(holdings.len());
+
+            for (account_id, detail) in &holdings {
+                let pos = &detail.position;
+                let mv = pos.current_price * pos.quantity * detail.multiplier;
+                quantity += pos.quantity;
+                cost_basis += pos.average_price * pos.quantity * detail.multiplier;
+                market_value += mv;
+                gross_value += mv.abs();
+                unrealized_pnl += pos.unrealized_pnl;
+                contributions.push(AccountContribution {
+                    account_id: account_id.to_string(),
+                    quantity: pos.quantity,
+                    average_price: pos.average_price,
+                    market_value: mv,
+                    pct_of_symbol: 0.0,
+                });
+            }
+
+            for c in &mut contributions {
+                c.pct_of_symbol = if gross_value != 0.0 {
+                    c.market_value.abs() / gross_value * 100.0
+                } else {
+                    0.0
+                };
+            }
+
+            let units = quantity * first.multiplier;
+            AggregatePosition {
+                symbol_id,
+                asset_class: first.asset_class,
+                quantity,
+                average_price: if units != 0.0 {
+                    cost_basis / units
+                } else {
+                    0.0
+                },
+                current_price: first.position.current_price,
+                cost_basis,
+                market_value,
+                unrealized_pnl,
+                allocation_pct: 0.0,
+                accounts: contributions,
+            }
+        })
+        .collect();
+
+    let gross_market_value: f64 = positions.iter().map(|p| p.market_value.abs()).sum();
+    for p in &mut positions {
+        p.allocation_pct = if gross_market_value != 0.0 {
+            p.market_value.abs() / gross_market_value * 100.0
+        } else {
+            0.0
+        };
+    }
+    positions.sort_by(|a, b| {
+        b.allocation_pct
+            .partial_cmp(&a.allocation_pct)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    AggregatePortfolio {
+        total_market_value: positions.iter().map(|p| p.market_value).sum(),
+        gross_market_value,
+        total_cost_basis: positions.iter().map(|p| p.cost_basis).sum(),
+        total_unrealized_pnl: positions.iter().map(|p| p.unrealized_pnl).sum(),
+        account_count: accounts.len(),
+        positions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use models::portfolio::Position;
+    use std::collections::HashMap;
+
+    fn detail(
+        symbol: &str,
+        asset_class: AssetClass,
+        quantity: f64,
+        average_price: f64,
+        current_price: f64,
+    ) -> PositionDetail {
+        let multiplier = if asset_class == AssetClass::Option {
+            100.0
+        } else {
+            1.0
+        };
+        PositionDetail {
+            position: Position {
+                symbol_id: symbol.to_string(),
+                quantity,
+                average_price,
+                current_price,
+                unrealized_pnl: (current_price - average_price) * quantity * multiplier,
+                unrealized_pnl_percent: 0.0,
+            },
+            asset_class,
+            multiplier,
+            position_id: None,
+            lots_url: None,
+            extensions: HashMap::new(),
+        }
+    }
+
+    fn find<'a>(portfolio: &'a AggregatePortfolio, symbol: &str) -> &'a AggregatePosition {
+        portfolio
+            .positions
+            .iter()
+            .find(|p| p.symbol_id == symbol)
+            .unwrap()
+    }
+
+    #[test]
+    fn merges_a_symbol_held_in_several_accounts() {
+        let accounts = vec![
+            (
+                "A".to_string(),
+                vec![detail("AAPL", AssetClass::Equity, 10.0, 100.0, 200.0)],
+            ),
+            (
+                "B".to_string(),
+                vec![
+                    detail("AAPL", AssetClass::Equity, 30.0, 200.0, 200.0),
+                    detail("MSFT", AssetClass::Equity, 5.0, 400.0, 400.0),
+                ],
+            ),
+        ];
+        let portfolio = aggregate_positions(&accounts);
+
+        assert_eq!(portfolio.account_count, 2);
+        assert_eq!(portfolio.positions.len(), 2);
+
+        let aapl = find(&portfolio, "AAPL");
+        assert_eq!(aapl.quantity, 40.0);
+        assert_eq!(aapl.cost_basis, 7000.0);
+        // (10 x 100 + 30 x 200) / 40
+        assert_eq!(aapl.average_price, 175.0);
+        assert_eq!(aapl.market_value, 8000.0);
+        assert_eq!(aapl.unrealized_pnl, 1000.0);
+
+        let shares: Vec<(&str, f64)> = aapl
+            .accounts
+            .iter()
+            .map(|c| (c.account_id.as_str(), c.pct_of_symbol))
+            .collect();
+        assert_eq!(shares, [("A", 25.0), ("B", 75.0)]);
+
+        assert_eq!(portfolio.total_market_value, 10000.0);
+        assert_eq!(portfolio.total_cost_basis, 9000.0);
+        assert_eq!(portfolio.positions[0].symbol_id, "AAPL");
+        assert_eq!(aapl.allocation_pct, 80.0);
+    }
+
+    #[test]
+    fn weights_option_cost_by_contract_multiplier() {
+        let option = "AAPL 240621C00200000";
+        let accounts = vec![
+            (
+                "A".to_string(),
+                vec![detail(option, AssetClass::Option, 1.0, 2.0, 3.0)],
+            ),
+            (
+                "B".to_string(),
+                vec![detail(option, AssetClass::Option, 3.0, 4.0, 3.0)],
+            ),
+        ];
+        let portfolio = aggregate_positions(&accounts);
+
+        let position = find(&portfolio, option);
+        assert_eq!(position.quantity, 4.0);
+        assert_eq!(position.cost_basis, 1400.0);
+        assert_eq!(position.average_price, 3.5);
+        assert_eq!(position.market_value, 1200.0);
+    }
+
+    #[test]
+    fn allocation_uses_gross_exposure_for_shorts() {
+        let accounts = vec![(
+            "A".to_string(),
+            vec![
+                detail("AAPL", AssetClass::Equity, 10.0, 100.0, 100.0),
+                detail("TSLA", AssetClass::Equity, -5.0, 200.0, 200.0),
+            ],
+        )];
+        let portfolio = aggregate_positions(&accounts);
+
+        assert_eq!(portfolio.total_market_value, 0.0);
+        assert_eq!(portfolio.gross_market_value, 2000.0);
+        assert_eq!(find(&portfolio, "TSLA").allocation_pct, 50.0);
+        assert_eq!(find(&portfolio, "TSLA").average_price, 200.0);
+    }
+}
@@ -0,0 +1,371 @@
+//! Balance model
+//!
+//! Wire types for `/v1/accounts/{id}/balance` and the mapping of the
+//! `Computed` block onto `AccountBalance`. Which buying power applies depends
+//! on the account mode; everything else is kept in extensions.
+
+use models::portfolio::AccountBalance;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AccountMode {
+    Cash,
+    Margin,
+    /// Margin account flagged as pattern day trader
+    Pdt,
+}
+
+impl AccountMode {
+    /// Resolve the mode from E*TRADE's `accountMode` and `dayTraderStatus`
+    pub fn from_etrade(
+        account_mode: Option<&str>,
+        day_trader_status: Option<&str>,
+    ) -> Option<Self> {
+        let is_pdt = day_trader_status
+            .map(|s| {
+                let s = s.to_uppercase();
+                (s.contains("PDT") || s.contains("PATTERN_DAY_TRADER")) && !s.starts_with("NO")
+            })
+            .unwrap_or(false);
+
+        match account_mode.map(|m| m.to_uppercase()).as_deref() {
+            Some("CASH") => Some(AccountMode::Cash),
+            Some("MARGIN") if is_pdt => Some(AccountMode::Pdt),
+            Some("MARGIN") => Some(AccountMode::Margin),
+            Some(m) if m.contains("PDT") => Some(AccountMode::Pdt),
+            _ => None,
+        }
+    }
+}
+
+// --- E*TRADE wire format ---
+
+#[derive(Deserialize)]
+pub(crate) struct BalanceResponse {
+    #[serde(rename = "BalanceResponse")]
+    pub response: BalanceInner,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct BalanceInner {
+    #[serde(rename = "accountMode")]
+    pub account_mode: Option<String>,
+    #[serde(rename = "dayTraderStatus")]
+    pub day_trader_status: Option<String>,
+    #[serde(rename = "Computed")]
+    pub computed: Option<ComputedBalance>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ComputedBalance {
+    #[serde(rename = "cashAvailableForInvestment")]
+    pub cash_available_for_investment: Option<f64>,
+    #[serde(rename = "cashAvailableForWithdrawal")]
+    pub cash_available_for_withdrawal: Option<f64>,
+    #[serde(rename = "netCash")]
+    pub net_cash: Option<f64>,
+    #[serde(rename = "cashBalance")]
+    pub cash_balance: Option<f64>,
+    #[serde(rename = "settledCashForInvestment")]
+    pub settled_cash_for_investment: Option<f64>,
+    #[serde(rename = "unSettledCashForInvestment")]
+    pub unsettled_cash_for_investment: Option<f64>,
+    #[serde(rename = "fundsWithheldFromPurchasePower")]
+    pub funds_withheld_from_purchase_power: Option<f64>,
+    #[serde(rename = "fundsWithheldFromWithdrawal")]
+    pub funds_withheld_from_withdrawal: Option<f64>,
+    #[serde(rename = "cashBuyingPower")]
+    pub cash_buying_power: Option<f64>,
+    #[serde(rename = "marginBuyingPower")]
+    pub margin_buying_power: Option<f64>,
+    #[serde(rename = "dtCashBuyingPower")]
+    pub dt_cash_buying_power: Option<f64>,
+    #[serde(rename = "dtMarginBuyingPower")]
+    pub dt_margin_buying_power: Option<f64>,
+    #[serde(rename = "marginBalance")]
+    pub margin_balance: Option<f64>,
+    #[serde(rename = "shortAdjustBalance")]
+    pub short_adjust_balance: Option<f64>,
+    #[serde(rename = "regtEquity")]
+    pub regt_equity: Option<f64>,
+    #[serde(rename = "regtEquityPercent")]
+    pub regt_equity_percent: Option<f64>,
+    #[serde(rename = "accountBalance")]
+    pub account_balance: Option<f64>,
+    #[serde(rename = "OpenCalls")]
+    pub open_calls: Option<OpenCalls>,
+    #[serde(rename = "RealTimeValues")]
+    pub real_time: Option<RealTimeValues>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct OpenCalls {
+    #[serde(rename = "minEquityCall", skip_serializing_if = "Option::is_none")]
+    pub min_equity_call: Option<f64>,
+    #[serde(rename = "fedCall", skip_serializing_if = "Option::is_none")]
+    pub fed_call: Option<f64>,
+    #[serde(rename = "cashCall", skip_serializing_if = "Option::is_none")]
+    pub cash_call: Option<f64>,
+    #[serde(rename = "houseCall", skip_serializing_if = "Option::is_none")]
+    pub house_call: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct RealTimeValues {
+    #[serde(rename = "totalAccountValue")]
+    pub total_account_value: Option<f64>,
+    #[serde(rename = "netMv")]
+    pub net_mv: Option<f64>,
+    #[serde(rename = "netMvLong")]
+    pub net_mv_long: Option<f64>,
+    #[serde(rename = "netMvShort")]
+    pub net_mv_short: Option<f64>,
+    #[serde(rename = "totalLongValue")]
+    pub total_long_value: Option<f64>,
+}
+
+/// Map a balance response onto `AccountBalance` plus extensions.
+///
+/// `known_mode` (from the account list) takes precedence over the mode
+/// reported in the balance response.
+pub(crate) fn map_balance(
+    inner: BalanceInner,
+    known_mode: Option<AccountMode>,
+) -> (AccountBalance, HashMap<String, serde_json::Value>) {
+    let computed = inner.computed;
+    let c = computed.as_ref();
+    let rt = c.and_then(|c| c.real_time.as_ref());
+
+    let mode = AccountMode::from_etrade(
+        inner.account_mode.as_deref(),
+        inner.day_trader_status.as_deref(),
+    )
+    .map(|m| match (known_mode, m) {
+        // The list only knows CASH/MARGIN; PDT comes from the balance call
+        (Some(AccountMode::Margin), AccountMode::Pdt) => AccountMode::Pdt,
+        (Some(k), _) => k,
+        (None, m) => m,
+    })
+    .or(known_mode)
+    .unwrap_or_else(|| {
+        if c.and_then(|c| c.margin_buying_power).unwrap_or(0.0) > 0.0 {
+            AccountMode::Margin
+        } else {
+            AccountMode::Cash
+        }
+    });
+
+    let field = |f: fn(&ComputedBalance) -> Option<f64>| c.and_then(f);
+
+    let total_equity = rt
+        .and_then(|r| r.total_account_value)
+        .or_else(|| field(|c| c.account_balance))
+        .unwrap_or(0.0);
+    let available_cash = field(|c| c.cash_available_for_investment)
+        .or_else(|| field(|c| c.net_cash))
+        .unwrap_or(0.0);
+    let buying_power = match mode {
+        AccountMode::Cash => {
+            field(|c| c.cash_buying_power).or_else(|| field(|c| c.cash_available_for_investment))
+        }
+        AccountMode::Margin => field(|c| c.margin_buying_power),
+        AccountMode::Pdt => field(|c| c.dt_margin_buying_power)
+            .or_else(|| field(|c| c.dt_cash_buying_power))
+            .or_else(|| field(|c| c.margin_buying_power)),
+    }
+    .unwrap_or(0.0);
+    let locked_cash = field(|c| c.funds_withheld_from_purchase_power)
+        .unwrap_or(0.0)
+        .abs();
+
+    let mut extensions = HashMap::new();
+    extensions.insert("account_mode".to_string(), serde_json::json!(mode));
+    if let Some(status) = &inner.day_trader_status {
+        extensions.insert("day_trader_status".to_string(), serde_json::json!(status));
+    }
+
+    let mut insert = |key: &str, value: Option<f64>| {
+        if let Some(v) = value {
+            extensions.insert(key.to_string(), serde_json::json!(v));
+        }
+    };
+    insert(
+        "cash_available_for_investment",
+        field(|c| c.cash_available_for_investment),
+    );
+    insert(
+        "cash_available_for_withdrawal",
+        field(|c| c.cash_available_for_withdrawal),
+    );
+    insert("net_cash", field(|c| c.net_cash));
+    insert("cash_balance", field(|c| c.cash_balance));
+    insert(
+        "settled_cash_for_investment",
+        field(|c| c.settled_cash_for_investment),
+    );
+    insert(
+        "unsettled_cash_for_investment",
+        field(|c| c.unsettled_cash_for_investment),
+    );
+    insert(
+        "funds_withheld_from_purchase_power",
+        field(|c| c.funds_withheld_from_purchase_power),
+    );
+    insert(
+        "funds_withheld_from_withdrawal",
+        field(|c| c.funds_withheld_from_withdrawal),
+    );
+    insert("cash_buying_power", field(|c| c.cash_buying_power));
+    insert("margin_buying_power", field(|c| c.margin_buying_power));
+    insert("dt_cash_buying_power", field(|c| c.dt_cash_buying_power));
+    insert(
+        "dt_margin_buying_power",
+        field(|c| c.dt_margin_buying_power),
+    );
+    insert("margin_balance", field(|c| c.margin_balance));
+    insert("short_adjust_balance", field(|c| c.short_adjust_balance));
+    insert("regt_equity", field(|c| c.regt_equity));
+    insert("regt_equity_percent", field(|c| c.regt_equity_percent));
+    insert("net_market_value", rt.and_then(|r| r.net_mv));
+    insert("net_market_value_long", rt.and_then(|r| r.net_mv_long));
+    insert("net_market_value_short", rt.and_then(|r| r.net_mv_short));
+    insert("total_long_value", rt.and_then(|r| r.total_long_value));
+
+    if let Some(calls) = c.and_then(|c| c.open_calls.as_ref()) {
+        extensions.insert(
+            "open_calls".to_string(),
+            serde_json::to_value(calls).unwrap_or_default(),
+        );
+    }
+
+    let balance = AccountBalance {
+        currency: "USD".to_string(),
+        total_equity,
+        available_cash,
+        buying_power,
+        locked_cash,
+    };
+
+    (balance, extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inner(json: serde_json::Value) -> BalanceInner {
+        serde_json::from_value::<BalanceResponse>(json)
+            .unwrap()
+            .response
+    }
+
+    fn cash_fixture() -> BalanceInner {
+        inner(serde_json::json!({"BalanceResponse": {
+            "accountMode": "CASH",
+            "Computed": {
+                "cashAvailableForInvestment": 1500.0,
+                "cashAvailableForWithdrawal": 1200.0,
+                "netCash": 1600.0,
+                "cashBuyingPower": 1450.0,
+                "marginBuyingPower": 0.0,
+                "fundsWithheldFromPurchasePower": -50.0,
+                "RealTimeValues": {"totalAccountValue": 10000.0, "netMv": 8400.0}
+            }
+        }}))
+    }
+
+    fn margin_fixture(day_trader_status: &str) -> BalanceInner {
+        inner(serde_json::json!({"BalanceResponse": {
+            "accountMode": "MARGIN",
+            "dayTraderStatus": day_trader_status,
+            "Computed": {
+                "cashAvailableForInvestment": 3000.0,
+                "netCash": 3000.0,
+                "cashBuyingPower": 3000.0,
+                "marginBuyingPower": 6000.0,
+                "dtCashBuyingPower": 9000.0,
+                "dtMarginBuyingPower": 12000.0,
+                "marginBalance": -500.0,
+                "accountBalance": 20000.0,
+                "OpenCalls": {"fedCall": 250.0}
+            }
+        }}))
+    }
+
+    #[test]
+    fn cash_account_uses_cash_buying_power() {
+        let (balance, extensions) = map_balance(cash_fixture(), None);
+
+        assert_eq!(balance.total_equity, 10000.0);
+        assert_eq!(balance.available_cash, 1500.0);
+        assert_eq!(balance.buying_power, 1450.0);
+        assert_eq!(balance.locked_cash, 50.0);
+        assert_eq!(extensions["account_mode"], "CASH");
+        assert_eq!(extensions["cash_available_for_withdrawal"], 1200.0);
+        assert_eq!(extensions["net_market_value"], 8400.0);
+        assert!(!extensions.contains_key("day_trader_status"));
+    }
+
+    #[test]
+    fn margin_account_uses_margin_buying_power() {
+        let (balance, extensions) = map_balance(margin_fixture("NO_PDT"), None);
+
+        assert_eq!(balance.total_equity, 20000.0);
+        assert_eq!(balance.available_cash, 3000.0);
+        assert_eq!(balance.buying_power, 6000.0);
+        assert_eq!(extensions["account_mode"], "MARGIN");
+        assert_eq!(extensions["margin_balance"], -500.0);
+        assert_eq!(extensions["open_calls"]["fedCall"], 250.0);
+    }
+
+    #[test]
+    fn pattern_day_trader_uses_day_trading_buying_power() {
+        for status in ["PDT_MIN_EQUITY_RES_1ST_CALL", "PATTERN_DAY_TRADER"] {
+            let (balance, extensions) = map_balance(margin_fixture(status), None);
+            assert_eq!(balance.buying_power, 12000.0, "{}", status);
+            assert_eq!(extensions["account_mode"], "PDT", "{}", status);
+            assert_eq!(extensions["day_trader_status"], status);
+        }
+    }
+
+    #[test]
+    fn day_trader_status_detection() {
+        let mode = |status| AccountMode::from_etrade(Some("MARGIN"), Some(status));
+        assert_eq!(mode("PDT_CLOSING_ONLY"), Some(AccountMode::Pdt));
+        assert_eq!(mode("NO_PDT"), Some(AccountMode::Margin));
+        assert_eq!(mode("NON_PATTERN_DAY_TRADER"), Some(AccountMode::Margin));
+        assert_eq!(
+            AccountMode::from_etrade(Some("CASH"), Some("PDT_CLOSING_ONLY")),
+            Some(AccountMode::Cash)
+        );
+        assert_eq!(AccountMode::from_etrade(None, None), None);
+    }
+
+    #[test]
+    fn account_list_mode_wins_except_for_pdt() {
+        let (balance, extensions) = map_balance(margin_fixture("NO_PDT"), Some(AccountMode::Cash));
+        assert_eq!(balance.buying_power, 3000.0);
+        assert_eq!(extensions["account_mode"], "CASH");
+
+        let (balance, extensions) = map_balance(
+            margin_fixture("PATTERN_DAY_TRADER"),
+            Some(AccountMode::Margin),
+        );
+        assert_eq!(balance.buying_power, 12000.0);
+        assert_eq!(extensions["account_mode"], "PDT");
+    }
+
+    #[test]
+    fn mode_falls_back_to_margin_buying_power() {
+        let fixture = || {
+            inner(serde_json::json!({"BalanceResponse": {
+                "Computed": {"marginBuyingPower": 800.0, "cashBuyingPower": 400.0}
+            }}))
+        };
+        let (balance, extensions) = map_balance(fixture(), None);
+        assert_eq!(extensions["account_mode"], "MARGIN");
+        assert_eq!(balance.buying_power, 800.0);
+    }
+}
@@ -0,0 +1,273 @@
+//! Circuit breaker for the E*TRADE request layer
+//!
+//! After `failure_threshold` consecutive transport failures or 5xx
+//! responses the circuit opens and requests fail immediately with
+//! `circuit_open` instead of waiting for the host timeout. Once the cooldown
+//! has elapsed the circuit half-opens: the next request is sent, and its
+//! outcome closes or re-opens the circuit.
+
+use crate::common::now_ms;
+use crate::error::PluginError;
+use crate::http::HttpResponse;
+use crate::trace::{self, LogLevel};
+use serde::Serialize;
+
+pub const DEFAULT_CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+pub const DEFAULT_CIRCUIT_COOLDOWN_MS: i64 = 30000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    pub cooldown_ms: i64,
+    /// Time until an open circuit half-opens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in_ms: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+pub struct CircuitBreaker {
+    /// 0 disables the breaker
+    failure_threshold: u32,
+    cooldown_ms: i64,
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at_ms: i64,
+    last_error: Option<String>,
+    /// Current time in epoch milliseconds
+    clock: fn() -> i64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::from_config(&serde_json::Value::Null)
+    }
+}
+
+impl CircuitBreaker {
+    /// Read `circuit_failure_threshold` and `circuit_cooldown_ms` from the
+    /// `initialize` config
+    pub fn from_config(config: &serde_json::Value) -> Self {
+        Self {
+            failure_threshold: config
+                .get("circuit_failure_threshold")
+                .and_then(|v| v.as_u64())
+                .map(|n| n.min(u32::MAX as u64) as u32)
+                .unwrap_or(DEFAULT_CIRCUIT_FAILURE_THRESHOLD),
+            cooldown_ms: config
+                .get("circuit_cooldown_ms")
+                .and_then(|v| v.as_i64())
+                .unwrap_or(DEFAULT_CIRCUIT_COOLDOWN_MS),
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at_ms: 0,
+            last_error: None,
+            clock: now_ms,
+        }
+    }
+
+    fn retry_in_ms(&self, now_ms: i64) -> i64 {
+        (self.opened_at_ms + self.cooldown_ms - now_ms).max(0)
+    }
+
+    /// Fail fast while open; half-open once the cooldown has elapsed
+    pub fn check(&mut self) -> Result<(), PluginError> {
+        if self.state != CircuitState::Open {
+            return Ok(());
+        }
+
+        let retry_in_ms = self.retry_in_ms((self.clock)());
+        if retry_in_ms > 0 {
+            return Err(PluginError::CircuitOpen {
+                message: format!(
+                    "E*TRADE unavailable after {} consecutive failures: {}",
+                    self.consecutive_failures,
+                    self.last_error.as_deref().unwrap_or("unknown error")
+                ),
+                retry_in_ms,
+            });
+        }
+
+        self.state = CircuitState::HalfOpen;
+        Ok(())
+    }
+
+    /// Record the outcome of a request that was sent
+    pub fn record(&mut self, response: &HttpResponse) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+
+        let failed = response.error.is_some() || response.status >= 500;
+        if !failed {
+            self.state = CircuitState::Closed;
+            self.consecutive_failures = 0;
+            return;
+        }
+
+        self.consecutive_failures += 1;
+        self.last_error = Some(match &response.error {
+            Some(e) => e.clone(),
+            None => format!("HTTP {}", response.status),
+        });
+
+        if self.state == CircuitState::HalfOpen
+            || self.consecutive_failures >= self.failure_threshold
+        {
+            if self.state != CircuitState::Open {
+                trace::log(
+                    LogLevel::Error,
+                    &format!(
+                        "Circuit opened after {} consecutive failures",
+                        self.consecutive_failures
+                    ),
+                );
+            }
+            self.state = CircuitState::Open;
+            self.opened_at_ms = (self.clock)();
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let now_ms = (self.clock)();
+        CircuitStatus {
+            state: self.state,
+            consecutive_failures: self.consecutive_failures,
+            failure_threshold: self.failure_threshold,
+            cooldown_ms: self.cooldown_ms,
+            retry_in_ms: (self.state == CircuitState::Open).then(|| self.retry_in_ms(now_ms)),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::response;
+    use std::cell::Cell;
+
+    thread_local! {
+        static NOW_MS: Cell<i64> = const { Cell::new(0) };
+    }
+
+    fn advance(ms: i64) {
+        NOW_MS.with(|now| now.set(now.get() + ms));
+    }
+
+    /// Breaker on a fake clock: opens after 2 failures, cools down in 1s
+    fn breaker() -> CircuitBreaker {
+        let mut breaker = CircuitBreaker::from_config(&serde_json::json!({
+            "circuit_failure_threshold": 2,
+            "circuit_cooldown_ms": 1000
+        }));
+        breaker.clock = || NOW_MS.with(Cell::get);
+        breaker
+    }
+
+    fn failure() -> HttpResponse {
+        response(503, "text/html", "")
+    }
+
+    fn success() -> HttpResponse {
+        response(200, "application/json", "{}")
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let mut breaker = breaker();
+
+        breaker.record(&failure());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert!(breaker.check().is_ok());
+
+        breaker.record(&failure());
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        match breaker.check() {
+            Err(PluginError::CircuitOpen { retry_in_ms, .. }) => assert_eq!(retry_in_ms, 1000),
+            other => panic!("expected circuit_open, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn successes_reset_the_failure_count() {
+        let mut breaker = breaker();
+
+        breaker.record(&failure());
+        breaker.record(&success());
+        breaker.record(&failure());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 1);
+    }
+
+    #[test]
+    fn stays_open_until_the_cooldown_elapses() {
+        let mut breaker = breaker();
+        breaker.record(&failure());
+        breaker.record(&failure());
+
+        advance(600);
+        match breaker.check() {
+            Err(PluginError::CircuitOpen { retry_in_ms, .. }) => assert_eq!(retry_in_ms, 400),
+            other => panic!("expected circuit_open, got {:?}", other),
+        }
+        assert_eq!(breaker.status().retry_in_ms, Some(400));
+
+        advance(400);
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn half_open_closes_on_success() {
+        let mut breaker = breaker();
+        breaker.record(&failure());
+        breaker.record(&failure());
+        advance(1000);
+
+        assert!(breaker.check().is_ok());
+        breaker.record(&success());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+    }
+
+    #[test]
+    fn half_open_reopens_on_failure_with_a_fresh_cooldown() {
+        let mut breaker = breaker();
+        breaker.record(&failure());
+        breaker.record(&failure());
+        advance(1000);
+
+        assert!(breaker.check().is_ok());
+        breaker.record(&HttpResponse::local_error("connection reset".to_string()));
+        assert_eq!(breaker.status().state, CircuitState::Open);
+        assert_eq!(breaker.status().retry_in_ms, Some(1000));
+        assert_eq!(
+            breaker.status().last_error.as_deref(),
+            Some("connection reset")
+        );
+        assert!(breaker.check().is_err());
+    }
+
+    #[test]
+    fn threshold_zero_disables_the_breaker() {
+        let mut breaker = CircuitBreaker::from_config(&serde_json::json!({
+            "circuit_failure_threshold": 0
+        }));
+        for _ in 0..10 {
+            breaker.record(&failure());
+        }
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+    }
+}
@@ -0,0 +1,25 @@
+//! Helpers shared across modules: E*TRADE value mapping and the clock
+
+use chrono::{DateTime, TimeZone, Utc};
+
+/// Holding period (in days) after which a lot or gain is long-term
+pub const LONG_TERM_DAYS: i64 = 365;
+
+/// E*TRADE returns IDs as either numbers or strings
+pub fn value_to_string(v: &serde_json::Value) -> String {
+    match v {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// E*TRADE timestamps are milliseconds since the epoch
+pub fn millis_to_utc(ms: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(ms).single()
+}
+
+/// Current time in epoch milliseconds; the default clock of the rate
+/// limiter and circuit breaker
+pub fn now_ms() -> i64 {
+    Utc::now().timestamp_millis()
+}
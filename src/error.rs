@@ -0,0 +1,369 @@
+//! Plugin error types
+//!
+//! Every failure the host can see is a `PluginError`, serialized as
+//! `{"kind": "...", "message": "...", ...}` so the host can branch on
+//! `kind` instead of matching message text. Errors that stop an export
+//! before it produces a typed response are wrapped in an `ErrorEnvelope`.
+//!
+//! Non-2xx E*TRADE responses are classified by status and by the
+//! `{"Error": {"code": ..., "message": ...}}` body E*TRADE returns. The same
+//! fields are read from XML error bodies, and `oauth_problem` from the
+//! form-encoded bodies of the OAuth endpoints.
+
+use crate::http::{ContentKind, HttpResponse};
+use crate::oauth;
+use crate::timeouts::Operation;
+use crate::xml;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PluginError {
+    /// Host payload could not be read or deserialized
+    InvalidRequest { message: String },
+    /// Response could not be serialized
+    Serialization { message: String },
+    /// `initialize` has not been called with OAuth credentials
+    NotInitialized { message: String },
+    /// Credentials were rejected (401/403)
+    Auth { status: u16, message: String },
+    /// Access token expired or was revoked; the OAuth flow must be redone
+    TokenExpired { message: String },
+    /// E*TRADE throttled the request (429)
+    RateLimited {
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after_secs: Option<u64>,
+    },
+    /// Request parameters were rejected before calling E*TRADE
+    Validation { message: String },
+    /// E*TRADE processed the request and refused it
+    BrokerRejected {
+        status: u16,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        code: Option<i64>,
+        message: String,
+    },
+    /// Request exceeded the configured timeout for its operation
+    Timeout {
+        operation: Operation,
+        timeout_ms: u32,
+        message: String,
+    },
+    /// Transport failure or E*TRADE server error
+    Network {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+        message: String,
+    },
+    /// E*TRADE response body could not be decoded
+    Parse { message: String },
+    /// Request was not sent because the circuit breaker is open
+    CircuitOpen { message: String, retry_in_ms: i64 },
+    /// Result exceeded a plugin limit and could not be collected in full
+    Truncated { message: String },
+}
+
+impl PluginError {
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        PluginError::InvalidRequest {
+            message: message.into(),
+        }
+    }
+
+    pub fn serialization(message: impl Into<String>) -> Self {
+        PluginError::Serialization {
+            message: message.into(),
+        }
+    }
+
+    pub fn not_initialized() -> Self {
+        PluginError::NotInitialized {
+            message: "Plugin not initialized or OAuth not completed".to_string(),
+        }
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        PluginError::Validation {
+            message: message.into(),
+        }
+    }
+
+    pub fn parse(message: impl Into<String>) -> Self {
+        PluginError::Parse {
+            message: message.into(),
+        }
+    }
+
+    pub fn truncated(message: impl Into<String>) -> Self {
+        PluginError::Truncated {
+            message: message.into(),
+        }
+    }
+
+    /// Classify a failed E*TRADE response
+    pub fn from_response(response: &HttpResponse) -> Self {
+        if let Some(error) = &response.error {
+            return PluginError::Network {
+                status: None,
+                message: error.clone(),
+            };
+        }
+
+        let status = response.status;
+        let parsed = parse_error_body(response);
+        let code = parsed.as_ref().and_then(|(code, _)| *code);
+        let message = parsed.and_then(|(_, message)| message).unwrap_or_else(|| {
+            if response.body.trim().is_empty() {
+                format!("HTTP {}", status)
+            } else {
+                response.body.clone()
+            }
+        });
+
+        match status {
+            401 if is_token_expired(&response.body) => PluginError::TokenExpired { message },
+            401 | 403 => PluginError::Auth { status, message },
+            429 => PluginError::RateLimited {
+                message,
+                retry_after_secs: response.retry_after_secs(),
+            },
+            // Callers that know the operation report these as `Timeout`
+            408 => PluginError::Network {
+                status: Some(status),
+                message,
+            },
+            500..=599 if code.is_none() => PluginError::Network {
+                status: Some(status),
+                message,
+            },
+            _ => PluginError::BrokerRejected {
+                status,
+                code,
+                message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::InvalidRequest { message } => write!(f, "Invalid request: {}", message),
+            PluginError::Serialization { message } => {
+                write!(f, "Serialization error: {}", message)
+            }
+            PluginError::NotInitialized { message } => write!(f, "{}", message),
+            PluginError::Auth { status, message } => {
+                write!(f, "Authentication failed ({}): {}", status, message)
+            }
+            PluginError::TokenExpired { message } => write!(f, "Token expired: {}", message),
+            PluginError::RateLimited { message, .. } => write!(f, "Rate limited: {}", message),
+            PluginError::Validation { message } => write!(f, "Validation error: {}", message),
+            PluginError::BrokerRejected {
+                status,
+                code: Some(code),
+                message,
+            } => write!(f, "E*TRADE error {} ({}): {}", code, status, message),
+            PluginError::BrokerRejected {
+                status, message, ..
+            } => {
+                write!(f, "E*TRADE error ({}): {}", status, message)
+            }
+            PluginError::Timeout {
+                operation,
+                timeout_ms,
+                message,
+            } => write!(
+                f,
+                "Timed out after {}ms ({:?}): {}",
+                timeout_ms, operation, message
+            ),
+            PluginError::Network {
+                status: Some(status),
+                message,
+            } => write!(f, "Network error ({}): {}", status, message),
+            PluginError::Network { message, .. } => write!(f, "Network error: {}", message),
+            PluginError::Parse { message } => write!(f, "Parse error: {}", message),
+            PluginError::CircuitOpen {
+                message,
+                retry_in_ms,
+            } => {
+                write!(f, "Circuit open (retry in {}ms): {}", retry_in_ms, message)
+            }
+            PluginError::Truncated { message } => write!(f, "Result truncated: {}", message),
+        }
+    }
+}
+
+/// Extract `code` and `message` from an error body in whichever format
+/// E*TRADE sent it
+fn parse_error_body(response: &HttpResponse) -> Option<(Option<i64>, Option<String>)> {
+    let body = &response.body;
+    match response.content_kind() {
+        ContentKind::Json => parse_json_error(body),
+        ContentKind::Xml => {
+            let scope = xml::element_inner(body, "Error").unwrap_or(body);
+            let code = xml::element_text(scope, "code").and_then(|c| c.parse().ok());
+            let message = xml::element_text(scope, "message");
+            (code.is_some() || message.is_some()).then_some((code, message))
+        }
+        // Gateway error pages; the title is more useful than the markup
+        ContentKind::Html => Some((
+            None,
+            Some(
+                xml::element_text(body, "title")
+                    .unwrap_or_else(|| format!("HTTP {}", response.status)),
+            ),
+        )),
+        ContentKind::Form => {
+            let form = oauth::parse_form(body);
+            let problem = form.get("oauth_problem")?;
+            let message = match form.get("oauth_problem_advice") {
+                Some(advice) => format!("{}: {}", problem, advice),
+                None => problem.clone(),
+            };
+            Some((None, Some(message)))
+        }
+        ContentKind::Text | ContentKind::Empty => None,
+    }
+}
+
+/// E*TRADE JSON error: `{"Error": {"code": ..., "message": ...}}`
+fn parse_json_error(body: &str) -> Option<(Option<i64>, Option<String>)> {
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = value.get("Error").or_else(|| value.get("error"))?;
+
+    let code = error.get("code").and_then(|c| {
+        c.as_i64()
+            .or_else(|| c.as_str().and_then(|s| s.trim().parse().ok()))
+    });
+    let message = error
+        .get("message")
+        .and_then(|m| m.as_str())
+        .map(|m| m.to_string());
+
+    Some((code, message))
+}
+
+/// OAuth failures are reported as `oauth_problem=token_expired` or
+/// `token_rejected`, both of which require renewing the access token
+fn is_token_expired(body: &str) -> bool {
+    let body = body.to_lowercase();
+    body.contains("token_expired") || body.contains("token_rejected")
+}
+
+/// Response body for a request that failed before producing a typed response
+#[derive(Debug, Serialize)]
+pub struct ErrorEnvelope {
+    pub success: bool,
+    pub error: PluginError,
+}
+
+impl ErrorEnvelope {
+    pub fn new(error: PluginError) -> Self {
+        Self {
+            success: false,
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::response;
+
+    #[test]
+    fn classifies_html_maintenance_pages_by_title() {
+        let page = response(
+            503,
+            "text/html",
+            "<!DOCTYPE html><html><head><title>Scheduled Maintenance</title></head>\
+             <body><h1>We'll be back soon</h1></body></html>",
+        );
+        match PluginError::from_response(&page) {
+            PluginError::Network { status, message } => {
+                assert_eq!(status, Some(503));
+                assert_eq!(message, "Scheduled Maintenance");
+            }
+            other => panic!("expected network error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn classifies_form_encoded_oauth_problems() {
+        let expired = response(
+            401,
+            "application/x-www-form-urlencoded",
+            "oauth_problem=token_expired&oauth_problem_advice=Renew%20the%20token",
+        );
+        match PluginError::from_response(&expired) {
+            PluginError::TokenExpired { message } => {
+                assert_eq!(message, "token_expired: Renew the token")
+            }
+            other => panic!("expected token_expired, got {:?}", other),
+        }
+
+        // No content type: sniffed as a form body
+        let bad_signature = response(401, "", "oauth_problem=signature_invalid");
+        match PluginError::from_response(&bad_signature) {
+            PluginError::Auth { status, message } => {
+                assert_eq!(status, 401);
+                assert_eq!(message, "signature_invalid");
+            }
+            other => panic!("expected auth error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_code_and_message_from_xml_errors() {
+        let rejected = response(
+            400,
+            "application/xml",
+            "<?xml version=\"1.0\"?><Error><code>1002</code>\
+             <message>Invalid symbol &amp; quantity</message></Error>",
+        );
+        match PluginError::from_response(&rejected) {
+            PluginError::BrokerRejected {
+                status,
+                code,
+                message,
+            } => {
+                assert_eq!(status, 400);
+                assert_eq!(code, Some(1002));
+                assert_eq!(message, "Invalid symbol & quantity");
+            }
+            other => panic!("expected broker_rejected, got {:?}", other),
+        }
+
+        // A coded 5xx is a broker answer, not an outage
+        let coded = response(500, "text/xml", "<Error><code>100</code></Error>");
+        assert!(matches!(
+            PluginError::from_response(&coded),
+            PluginError::BrokerRejected {
+                code: Some(100),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn timeouts_without_an_operation_are_network_errors() {
+        let timed_out = response(408, "text/plain", "");
+        match PluginError::from_response(&timed_out) {
+            PluginError::Network { status, message } => {
+                assert_eq!(status, Some(408));
+                assert_eq!(message, "HTTP 408");
+            }
+            other => panic!("expected network error, got {:?}", other),
+        }
+
+        let host_timeout = HttpResponse::local_error("Request timed out".to_string());
+        assert!(matches!(
+            PluginError::from_response(&host_timeout),
+            PluginError::Network { status: None, .. }
+        ));
+    }
+}
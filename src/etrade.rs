@@ -1,29 +1,234 @@
 //! E*TRADE API Client
 //!
-//! Implements OAuth 1.0a authentication and E*TRADE API endpoints.
-
-use crate::http::{execute, HttpMethod, HttpRequest};
-use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
-use chrono::Utc;
-use hmac::{Hmac, Mac};
+//! Implements E*TRADE API endpoints on top of `HttpClient`, which signs every
+//! request with OAuth 1.0a through `OAuthSigner`.
+
+use crate::balance::{map_balance, AccountMode, BalanceResponse};
+use crate::circuit_breaker::{CircuitBreaker, CircuitStatus};
+use crate::common::millis_to_utc;
+use crate::error::PluginError;
+use crate::http::{HttpClient, HttpMethod, HttpRequest, HttpResponse};
+use crate::oauth::{percent_encode, OAuthSigner};
+use crate::portfolio::{
+    PortfolioQuery, PortfolioResponse, PositionDetail, PositionLot, PositionLotsResponse,
+    MAX_PORTFOLIO_PAGES, PORTFOLIO_PAGE_SIZE,
+};
+use crate::rate_limit::{EndpointFamily, RateLimitStatus, RateLimiter};
+use crate::retry::RetryPolicy;
+use crate::timeouts::{Operation, Timeouts};
+use crate::trace::{self, redact_path, LogLevel, TraceEntry, Tracer};
+use crate::transactions::{
+    self, ETradeTransaction, Transaction, TransactionPage, TransactionQuery,
+};
+use chrono::{DateTime, Utc};
 use models::order::{Order, OrderRequest, OrderSide, OrderStatus, OrderType};
 use models::portfolio::{AccountBalance, AccountSummary, Position};
-use serde::Deserialize;
-use sha1::Sha1;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
 
-type HmacSha1 = Hmac<Sha1>;
-
 const PRODUCTION_URL: &str = "https://api.etrade.com";
 const SANDBOX_URL: &str = "https://apisb.etrade.com";
 
+/// Single match returned by the product lookup API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymbolLookupResult {
+    pub symbol: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "type", default)]
+    pub security_type: String,
+}
+
+/// Quote detail level (`detailFlag`) supported by the market quote API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QuoteDetail {
+    All,
+    Fundamental,
+    Intraday,
+    Options,
+    #[serde(rename = "WEEK_52")]
+    Week52,
+    MfDetail,
+}
+
+impl QuoteDetail {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuoteDetail::All => "ALL",
+            QuoteDetail::Fundamental => "FUNDAMENTAL",
+            QuoteDetail::Intraday => "INTRADAY",
+            QuoteDetail::Options => "OPTIONS",
+            QuoteDetail::Week52 => "WEEK_52",
+            QuoteDetail::MfDetail => "MF_DETAIL",
+        }
+    }
+}
+
+/// Market quote for a single symbol
+#[derive(Debug, Clone, Serialize)]
+pub struct Quote {
+    pub symbol: String,
+    pub detail: QuoteDetail,
+    pub last_price: Option<f64>,
+    pub bid: Option<f64>,
+    pub ask: Option<f64>,
+    pub change: Option<f64>,
+    pub volume: Option<f64>,
+    pub quote_status: Option<String>,
+    pub quoted_at: Option<i64>,
+    /// Raw detail block as returned by E*TRADE (`All`, `Intraday`, ...)
+    pub data: Option<serde_json::Value>,
+}
+
+/// Maximum symbols accepted by a single quote request
+pub const MAX_QUOTE_SYMBOLS: usize = 25;
+
+/// Balance and positions of a single account
+struct AccountDetails {
+    balance: AccountBalance,
+    extensions: HashMap<String, serde_json::Value>,
+    positions: Vec<PositionDetail>,
+    errors: Vec<AccountWarning>,
+}
+
+impl AccountDetails {
+    fn empty() -> Self {
+        Self {
+            balance: empty_balance(),
+            extensions: HashMap::new(),
+            positions: vec![],
+            errors: vec![],
+        }
+    }
+}
+
+fn empty_balance() -> AccountBalance {
+    AccountBalance {
+        currency: "USD".to_string(),
+        total_equity: 0.0,
+        available_cash: 0.0,
+        buying_power: 0.0,
+        locked_cash: 0.0,
+    }
+}
+
+/// Account metadata from `/v1/accounts/list`
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountInfo {
+    #[serde(rename = "accountId")]
+    pub account_id: String,
+    #[serde(rename = "accountIdKey")]
+    pub account_id_key: String,
+    #[serde(rename = "accountName")]
+    pub account_name: Option<String>,
+    #[serde(rename = "accountDesc")]
+    pub account_desc: Option<String>,
+    /// INDIVIDUAL, IRA, ROTHIRA, JOINT, ...
+    #[serde(rename = "accountType")]
+    pub account_type: Option<String>,
+    /// CASH or MARGIN
+    #[serde(rename = "accountMode")]
+    pub account_mode: Option<String>,
+    /// ACTIVE or CLOSED
+    #[serde(rename = "accountStatus")]
+    pub account_status: Option<String>,
+    /// BROKERAGE, ...; used as `instType` for the balance call
+    #[serde(rename = "institutionType")]
+    pub institution_type: Option<String>,
+}
+
+impl AccountInfo {
+    pub fn is_closed(&self) -> bool {
+        self.account_status
+            .as_deref()
+            .map(|s| s.eq_ignore_ascii_case("CLOSED"))
+            .unwrap_or(false)
+    }
+
+    pub fn inst_type(&self) -> &str {
+        self.institution_type
+            .as_deref()
+            .filter(|t| !t.is_empty())
+            .unwrap_or("BROKERAGE")
+    }
+
+    pub fn mode(&self) -> Option<AccountMode> {
+        AccountMode::from_etrade(self.account_mode.as_deref(), None)
+    }
+
+    pub fn display_name(&self) -> String {
+        self.account_name
+            .clone()
+            .filter(|n| !n.trim().is_empty())
+            .or_else(|| self.account_desc.clone().filter(|d| !d.trim().is_empty()))
+            .unwrap_or_else(|| format!("E*TRADE {}", self.account_id))
+    }
+
+    /// Metadata fields for `AccountSummary.extensions`
+    pub fn extensions(&self) -> HashMap<String, serde_json::Value> {
+        let mut map = HashMap::new();
+        map.insert(
+            "account_id_key".to_string(),
+            serde_json::Value::String(self.account_id_key.clone()),
+        );
+        let optional = [
+            ("account_type", &self.account_type),
+            ("account_mode", &self.account_mode),
+            ("account_status", &self.account_status),
+            ("institution_type", &self.institution_type),
+            ("account_desc", &self.account_desc),
+        ];
+        for (key, value) in optional {
+            if let Some(v) = value {
+                map.insert(key.to_string(), serde_json::Value::String(v.clone()));
+            }
+        }
+        map.insert(
+            "closed".to_string(),
+            serde_json::Value::Bool(self.is_closed()),
+        );
+        map
+    }
+}
+
+/// A loaded account, keeping the position details (asset class, contract
+/// multiplier) that `AccountSummary.positions` drops
+#[derive(Debug, Clone)]
+pub struct AccountSnapshot {
+    pub summary: AccountSummary,
+    pub positions: Vec<PositionDetail>,
+}
+
+/// Partial failure while loading one account
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountWarning {
+    pub account_id: String,
+    /// Which part failed to load (`balance` or `positions`)
+    pub source: String,
+    pub error: PluginError,
+}
+
+/// Accepted cancel request. E*TRADE cancels asynchronously, so the order
+/// may still fill before the cancel takes effect.
+#[derive(Debug, Clone, Serialize)]
+pub struct CancelledOrder {
+    pub order_id: String,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    /// Notices returned with the request
+    pub messages: Vec<String>,
+}
+
 pub struct ETradeClient {
-    consumer_key: String,
-    consumer_secret: String,
-    oauth_token: String,
-    oauth_token_secret: String,
-    base_url: String,
+    http: HttpClient,
     is_sandbox: bool,
+    include_closed_accounts: bool,
+    retry: RetryPolicy,
+    limiter: RefCell<RateLimiter>,
+    breaker: RefCell<CircuitBreaker>,
+    timeouts: Timeouts,
+    tracer: RefCell<Tracer>,
 }
 
 impl ETradeClient {
@@ -34,155 +239,262 @@ impl ETradeClient {
         oauth_token_secret: String,
         is_sandbox: bool,
     ) -> Self {
+        let base_url = if is_sandbox {
+            SANDBOX_URL
+        } else {
+            PRODUCTION_URL
+        };
+
         Self {
-            consumer_key,
-            consumer_secret,
-            oauth_token,
-            oauth_token_secret,
-            base_url: if is_sandbox {
-                SANDBOX_URL
-            } else {
-                PRODUCTION_URL
-            }
-            .to_string(),
+            http: HttpClient::new(base_url)
+                .header("Accept", "application/json")
+                .signer(OAuthSigner::new(
+                    consumer_key,
+                    consumer_secret,
+                    oauth_token,
+                    oauth_token_secret,
+                )),
             is_sandbox,
+            include_closed_accounts: false,
+            retry: RetryPolicy::default(),
+            limiter: RefCell::new(RateLimiter::default()),
+            breaker: RefCell::new(CircuitBreaker::default()),
+            timeouts: Timeouts::default(),
+            tracer: RefCell::new(Tracer::default()),
         }
     }
 
-    /// Generate OAuth 1.0a signature
-    fn generate_oauth_signature(
-        &self,
-        method: &str,
-        url: &str,
-        params: &[(String, String)],
-    ) -> String {
-        let mut sorted_params = params.to_vec();
-        sorted_params.sort_by(|a, b| a.0.cmp(&b.0));
-
-        let param_string: String = sorted_params
-            .iter()
-            .map(|(k, v)| format!("{}={}", percent_encode(k), percent_encode(v)))
-            .collect::<Vec<_>>()
-            .join("&");
+    /// Report closed accounts from `is_listed` (flagged `closed`)
+    pub fn include_closed_accounts(mut self, include: bool) -> Self {
+        self.include_closed_accounts = include;
+        self
+    }
 
-        let base_string = format!(
-            "{}&{}&{}",
-            method.to_uppercase(),
-            percent_encode(url),
-            percent_encode(&param_string)
-        );
+    /// Retry policy for GET requests
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
 
-        let signing_key = format!(
-            "{}&{}",
-            percent_encode(&self.consumer_secret),
-            percent_encode(&self.oauth_token_secret)
-        );
+    /// Per-endpoint-family rate limits
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = RefCell::new(limiter);
+        self
+    }
 
-        let mut mac = HmacSha1::new_from_slice(signing_key.as_bytes())
-            .expect("HMAC can take key of any size");
-        mac.update(base_string.as_bytes());
-        let result = mac.finalize();
+    /// Remaining request budget per endpoint family
+    pub fn rate_limit_status(&self) -> Vec<RateLimitStatus> {
+        self.limiter.borrow_mut().status()
+    }
 
-        BASE64.encode(result.into_bytes())
+    /// Circuit breaker for repeated E*TRADE failures
+    pub fn circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = RefCell::new(breaker);
+        self
     }
 
-    /// Build OAuth Authorization header
-    fn build_auth_header(&self, method: &str, url: &str) -> String {
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            .to_string();
+    /// Request timeouts per operation
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
 
-        let nonce = format!("{:016x}", rand::random::<u64>());
+    /// Call tracing and log level
+    pub fn tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = RefCell::new(tracer);
+        self
+    }
 
-        let mut oauth_params = vec![
-            ("oauth_consumer_key".to_string(), self.consumer_key.clone()),
-            ("oauth_token".to_string(), self.oauth_token.clone()),
-            (
-                "oauth_signature_method".to_string(),
-                "HMAC-SHA1".to_string(),
-            ),
-            ("oauth_timestamp".to_string(), timestamp),
-            ("oauth_nonce".to_string(), nonce),
-            ("oauth_version".to_string(), "1.0".to_string()),
-        ];
+    /// Most recent `limit` traced calls, oldest first
+    pub fn traces(&self, limit: usize) -> Vec<TraceEntry> {
+        self.tracer.borrow().recent(limit)
+    }
 
-        let signature = self.generate_oauth_signature(method, url, &oauth_params);
-        oauth_params.push(("oauth_signature".to_string(), signature));
+    pub fn circuit_status(&self) -> CircuitStatus {
+        self.breaker.borrow().status()
+    }
 
-        let header_value: String = oauth_params
-            .iter()
-            .map(|(k, v)| format!("{}=\"{}\"", k, percent_encode(v)))
-            .collect::<Vec<_>>()
-            .join(", ");
+    /// Check the circuit breaker, then wait for a rate limit token for the
+    /// endpoint family of `operation`
+    fn acquire(&self, operation: Operation) -> Result<(), PluginError> {
+        self.breaker.borrow_mut().check()?;
+        self.limiter
+            .borrow_mut()
+            .acquire(EndpointFamily::from(operation))
+    }
 
-        format!("OAuth {}", header_value)
+    /// Send a request through the circuit breaker and rate limiter. GETs
+    /// are retried per the retry policy; other methods are sent once since
+    /// order placement is not idempotent. The OAuth signer runs on every
+    /// attempt, so each retry gets a fresh nonce and timestamp.
+    fn send(
+        &self,
+        method: HttpMethod,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<HttpResponse, PluginError> {
+        let retryable = matches!(method, HttpMethod::Get);
+        let mut headers = HashMap::new();
+        if body.is_some() {
+            headers.insert("Content-Type".to_string(), "application/json".to_string());
+        }
+        let operation = Operation::from_path(path);
+        let mut attempt = 1;
+
+        loop {
+            self.acquire(operation)?;
+
+            let started_ms = Utc::now().timestamp_millis();
+            let response = self.http.send(HttpRequest {
+                method: method.clone(),
+                url: path.to_string(),
+                headers: headers.clone(),
+                body: body.clone(),
+                timeout_ms: self.timeouts.for_operation(operation),
+            });
+            let error = (!response.is_success()).then(|| self.response_error(path, &response));
+            self.tracer.borrow_mut().record(
+                &method,
+                path,
+                attempt,
+                &response,
+                error.as_ref(),
+                Utc::now().timestamp_millis() - started_ms,
+            );
+            self.breaker.borrow_mut().record(&response);
+
+            let delay = if retryable {
+                self.retry.next_delay(attempt, &response)
+            } else {
+                None
+            };
+            match delay {
+                Some(delay) => {
+                    trace::log(
+                        LogLevel::Info,
+                        &format!(
+                            "{} {} failed (status {}), retry {} in {}ms",
+                            method.as_str(),
+                            redact_path(path),
+                            response.status,
+                            attempt,
+                            delay.as_millis()
+                        ),
+                    );
+                    (self.retry.sleep)(delay);
+                    attempt += 1;
+                }
+                None => return Ok(response),
+            }
+        }
     }
 
-    fn api_get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, String> {
-        let url = format!("{}{}", self.base_url, path);
-        let auth_header = self.build_auth_header("GET", &url);
+    /// Classify a failed response; timeouts are reported with the
+    /// operation and limit that applied
+    fn response_error(&self, path: &str, response: &HttpResponse) -> PluginError {
+        if response.is_timeout() {
+            let operation = Operation::from_path(path);
+            return PluginError::Timeout {
+                operation,
+                timeout_ms: self.timeouts.for_operation(operation),
+                message: response
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| format!("HTTP {}", response.status)),
+            };
+        }
+        PluginError::from_response(response)
+    }
 
-        let mut headers = HashMap::new();
-        headers.insert("Authorization".to_string(), auth_header);
-        headers.insert("Accept".to_string(), "application/json".to_string());
-
-        let response = execute(HttpRequest {
-            method: HttpMethod::Get,
-            url,
-            headers,
-            body: None,
-            timeout_ms: 30000,
-        });
+    /// Send a request with an optional JSON body and decode the JSON
+    /// response. An empty body (e.g. 204) yields `None`.
+    fn api_request<T: serde::de::DeserializeOwned, B: serde::Serialize>(
+        &self,
+        method: HttpMethod,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<Option<T>, PluginError> {
+        let body = body
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|e| PluginError::serialization(e.to_string()))?;
+
+        let response = self.send(method, path, body)?;
 
         if !response.is_success() {
-            return Err(format!(
-                "API error {}: {}",
-                response.status,
-                response.error.unwrap_or(response.body)
-            ));
+            return Err(self.response_error(path, &response));
         }
 
-        response.json::<T>()
+        if response.status == 204 || response.body.trim().is_empty() {
+            return Ok(None);
+        }
+
+        response.json::<T>().map(Some).map_err(PluginError::parse)
+    }
+
+    fn api_get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, PluginError> {
+        self.api_get_optional(path)?
+            .ok_or_else(|| PluginError::parse("Empty response body"))
+    }
+
+    /// Like `api_get`, but maps an empty 204 response to `None`
+    fn api_get_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<Option<T>, PluginError> {
+        self.api_request::<T, ()>(HttpMethod::Get, path, None)
     }
 
     fn api_post<T: serde::de::DeserializeOwned, B: serde::Serialize>(
         &self,
         path: &str,
         body: &B,
-    ) -> Result<T, String> {
-        let url = format!("{}{}", self.base_url, path);
-        let auth_header = self.build_auth_header("POST", &url);
+    ) -> Result<T, PluginError> {
+        self.api_request(HttpMethod::Post, path, Some(body))?
+            .ok_or_else(|| PluginError::parse("Empty response body"))
+    }
 
-        let mut headers = HashMap::new();
-        headers.insert("Authorization".to_string(), auth_header);
-        headers.insert("Accept".to_string(), "application/json".to_string());
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
-
-        let body_str = serde_json::to_string(body).map_err(|e| e.to_string())?;
-
-        let response = execute(HttpRequest {
-            method: HttpMethod::Post,
-            url,
-            headers,
-            body: Some(body_str),
-            timeout_ms: 30000,
-        });
+    fn api_put<T: serde::de::DeserializeOwned, B: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, PluginError> {
+        self.api_request(HttpMethod::Put, path, Some(body))?
+            .ok_or_else(|| PluginError::parse("Empty response body"))
+    }
+
+    fn api_patch<T: serde::de::DeserializeOwned, B: serde::Serialize>(
+        &self,
+        path: &str,
+        body: &B,
+    ) -> Result<T, PluginError> {
+        self.api_request(HttpMethod::Patch, path, Some(body))?
+            .ok_or_else(|| PluginError::parse("Empty response body"))
+    }
+
+    /// DELETE usually answers with an empty body, hence the `Option`
+    fn api_delete<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> Result<Option<T>, PluginError> {
+        self.api_request::<T, ()>(HttpMethod::Delete, path, None)
+    }
+
+    /// Reactivate an access token that went idle (two hours without
+    /// requests). Tokens that expired at midnight ET cannot be renewed.
+    pub fn renew_access_token(&self) -> Result<(), PluginError> {
+        let path = "/oauth/renew_access_token";
+        let response = self.send(HttpMethod::Get, path, None)?;
 
         if !response.is_success() {
-            return Err(format!(
-                "API error {}: {}",
-                response.status,
-                response.error.unwrap_or(response.body)
-            ));
+            return Err(self.response_error(path, &response));
         }
 
-        response.json::<T>()
+        Ok(())
     }
 
-    /// List all accounts
-    pub fn list_accounts(&self) -> Result<Vec<AccountSummary>, String> {
+    /// List account metadata only (no balances or positions)
+    pub fn list_account_infos(&self) -> Result<Vec<AccountInfo>, PluginError> {
         #[derive(Deserialize)]
         struct AccountListResponse {
             #[serde(rename = "AccountListResponse")]
@@ -198,199 +510,456 @@ impl ETradeClient {
         #[derive(Deserialize)]
         struct AccountsWrapper {
             #[serde(rename = "Account")]
-            account: Vec<ETradeAccount>,
+            account: Vec<AccountInfo>,
         }
 
-        #[derive(Deserialize)]
-        struct ETradeAccount {
-            #[serde(rename = "accountId")]
-            account_id: String,
-            #[serde(rename = "accountIdKey")]
-            account_id_key: String,
-            #[serde(rename = "accountName")]
-            account_name: Option<String>,
+        let resp: AccountListResponse = self.api_get("/v1/accounts/list")?;
+
+        Ok(resp.response.accounts.account)
+    }
+
+    /// Whether an account from the list should be reported
+    pub fn is_listed(&self, account: &AccountInfo) -> bool {
+        !account.is_closed() || self.include_closed_accounts
+    }
+
+    /// Load one account with balance and positions
+    pub fn load_account(&self, acct: &AccountInfo) -> (AccountSnapshot, Vec<AccountWarning>) {
+        let AccountDetails {
+            balance,
+            extensions: balance_extensions,
+            positions,
+            errors,
+        } = if acct.is_closed() {
+            // Closed accounts have no balance or positions to load
+            AccountDetails::empty()
+        } else {
+            self.get_account_details(acct)
+        };
+        // Balance-derived fields (e.g. resolved PDT mode) win over list metadata
+        let mut extensions = acct.extensions();
+        extensions.extend(balance_extensions);
+        extensions.insert("details_loaded".to_string(), serde_json::Value::Bool(true));
+        extensions.insert(
+            "balance_available".to_string(),
+            serde_json::Value::Bool(
+                !acct.is_closed() && !errors.iter().any(|e| e.source == "balance"),
+            ),
+        );
+        extensions.insert(
+            "positions_available".to_string(),
+            serde_json::Value::Bool(
+                !acct.is_closed() && !errors.iter().any(|e| e.source == "positions"),
+            ),
+        );
+        if !errors.is_empty() {
+            extensions.insert(
+                "errors".to_string(),
+                serde_json::to_value(&errors).unwrap_or_default(),
+            );
         }
 
-        let resp: AccountListResponse = self.api_get("/v1/accounts/list")?;
+        let summary = AccountSummary {
+            id: acct.account_id.clone(),
+            name: acct.display_name(),
+            broker_id: "broker-etrade".to_string(),
+            is_paper: self.is_sandbox,
+            balance,
+            positions: positions.iter().map(|d| d.position.clone()).collect(),
+            updated_at: Utc::now(),
+            extensions: Some(extensions),
+        };
 
-        let mut accounts = Vec::new();
-        for acct in resp.response.accounts.account {
-            let (balance, positions) = self.get_account_details(&acct.account_id_key)?;
-
-            accounts.push(AccountSummary {
-                id: acct.account_id.clone(),
-                name: acct
-                    .account_name
-                    .unwrap_or_else(|| format!("E*TRADE {}", acct.account_id)),
-                broker_id: "broker-etrade".to_string(),
-                is_paper: self.is_sandbox,
-                balance,
-                positions,
-                updated_at: Utc::now(),
-                extensions: Some({
-                    let mut map = HashMap::new();
-                    map.insert(
-                        "account_id_key".to_string(),
-                        serde_json::Value::String(acct.account_id_key),
-                    );
-                    map
-                }),
-            });
+        (AccountSnapshot { summary, positions }, errors)
+    }
+
+    /// Account metadata only, without balance or positions requests
+    pub fn account_stub(&self, acct: &AccountInfo) -> AccountSummary {
+        let mut extensions = acct.extensions();
+        extensions.insert("details_loaded".to_string(), serde_json::Value::Bool(false));
+        extensions.insert(
+            "balance_available".to_string(),
+            serde_json::Value::Bool(false),
+        );
+        extensions.insert(
+            "positions_available".to_string(),
+            serde_json::Value::Bool(false),
+        );
+
+        AccountSummary {
+            id: acct.account_id.clone(),
+            name: acct.display_name(),
+            broker_id: "broker-etrade".to_string(),
+            is_paper: self.is_sandbox,
+            balance: empty_balance(),
+            positions: vec![],
+            updated_at: Utc::now(),
+            extensions: Some(extensions),
         }
+    }
+
+    /// Load balance and positions. Failures are recorded in `errors`
+    /// instead of failing the account, so the caller can tell a zeroed
+    /// balance apart from a real empty account.
+    fn get_account_details(&self, account: &AccountInfo) -> AccountDetails {
+        let account_id = &account.account_id;
+        let account_id_key = &account.account_id_key;
+        let mut errors = Vec::new();
+
+        let path = format!(
+            "/v1/accounts/{}/balance?instType={}&realTimeNAV=true",
+            account_id_key,
+            account.inst_type()
+        );
 
-        Ok(accounts)
+        let (balance, extensions) = match self.api_get::<BalanceResponse>(&path) {
+            Ok(resp) => map_balance(resp.response, account.mode()),
+            Err(e) => {
+                errors.push(AccountWarning {
+                    account_id: account_id.to_string(),
+                    source: "balance".to_string(),
+                    error: e,
+                });
+                (empty_balance(), HashMap::new())
+            }
+        };
+
+        // Get positions
+        let positions = match self.get_position_details(account_id_key, &PortfolioQuery::default())
+        {
+            Ok(p) => p,
+            Err(e) => {
+                errors.push(AccountWarning {
+                    account_id: account_id.to_string(),
+                    source: "positions".to_string(),
+                    error: e,
+                });
+                vec![]
+            }
+        };
+
+        AccountDetails {
+            balance,
+            extensions,
+            positions,
+            errors,
+        }
     }
 
-    fn get_account_details(
+    /// Get positions for an account
+    pub fn get_positions(&self, account_id: &str) -> Result<Vec<Position>, PluginError> {
+        Ok(self
+            .get_position_details(account_id, &PortfolioQuery::default())?
+            .into_iter()
+            .map(|d| d.position)
+            .collect())
+    }
+
+    /// Get positions with E*TRADE-specific fields (position ID, lots URL,
+    /// view data), following portfolio pages until all are collected
+    pub fn get_position_details(
         &self,
-        account_id_key: &str,
-    ) -> Result<(AccountBalance, Vec<Position>), String> {
-        #[derive(Deserialize)]
-        struct BalanceResponse {
-            #[serde(rename = "BalanceResponse")]
-            response: BalanceInner,
+        account_id: &str,
+        query: &PortfolioQuery,
+    ) -> Result<Vec<PositionDetail>, PluginError> {
+        let mut positions = Vec::new();
+        let mut page = 1;
+
+        loop {
+            let path = format!(
+                "/v1/accounts/{}/portfolio?{}",
+                account_id,
+                query.to_query_string(page)
+            );
+            let resp: PortfolioResponse = match self.api_get_optional(&path)? {
+                Some(r) => r,
+                None => break,
+            };
+
+            let portfolios = resp
+                .response
+                .and_then(|inner| inner.account_portfolio)
+                .unwrap_or_default();
+
+            let next = portfolios.iter().filter_map(|p| p.next_page(page)).max();
+            positions.extend(
+                portfolios
+                    .into_iter()
+                    .flat_map(|portfolio| portfolio.position.unwrap_or_default())
+                    .map(PositionDetail::from),
+            );
+
+            match next {
+                Some(n) if n > MAX_PORTFOLIO_PAGES => {
+                    return Err(PluginError::truncated(format!(
+                        "Portfolio has more than {} pages of {} positions",
+                        MAX_PORTFOLIO_PAGES, PORTFOLIO_PAGE_SIZE
+                    )));
+                }
+                Some(n) => page = n,
+                None => break,
+            }
         }
 
-        #[derive(Deserialize)]
-        struct BalanceInner {
-            #[serde(rename = "Computed")]
-            computed: Option<ComputedBalance>,
-        }
+        Ok(positions)
+    }
+
+    /// Get the individual tax lots of a position
+    pub fn get_position_lots(
+        &self,
+        account_id: &str,
+        position: &PositionDetail,
+    ) -> Result<Vec<PositionLot>, PluginError> {
+        let path = match (&position.lots_url, &position.position_id) {
+            (Some(url), _) => url.clone(),
+            (None, Some(id)) => format!("/v1/accounts/{}/portfolio/{}", account_id, id),
+            (None, None) => {
+                return Err(PluginError::validation(format!(
+                    "Position {} has no lots details",
+                    position.position.symbol_id
+                )))
+            }
+        };
 
+        let lots = match self.api_get_optional::<PositionLotsResponse>(&path)? {
+            Some(resp) => resp
+                .response
+                .and_then(|inner| inner.position_lot)
+                .unwrap_or_default()
+                .into_iter()
+                .map(PositionLot::from)
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(lots)
+    }
+
+    /// List one page of transactions for an account
+    pub fn list_transactions(
+        &self,
+        account_id: &str,
+        query: &TransactionQuery,
+    ) -> Result<TransactionPage, PluginError> {
         #[derive(Deserialize)]
-        struct ComputedBalance {
-            #[serde(rename = "RealTimeValues")]
-            real_time: Option<RealTimeValues>,
+        struct TransactionListResponse {
+            #[serde(rename = "TransactionListResponse")]
+            response: Option<TransactionListInner>,
         }
 
         #[derive(Deserialize)]
-        struct RealTimeValues {
-            #[serde(rename = "totalAccountValue")]
-            total_account_value: Option<f64>,
-            #[serde(rename = "netMv")]
-            net_mv: Option<f64>,
-            #[serde(rename = "totalLongValue")]
-            total_long_value: Option<f64>,
+        struct TransactionListInner {
+            #[serde(rename = "Transaction")]
+            transaction: Option<Vec<ETradeTransaction>>,
+            marker: Option<String>,
+            #[serde(rename = "moreTransactions")]
+            more_transactions: Option<bool>,
+            #[serde(rename = "totalCount")]
+            total_count: Option<i64>,
         }
 
         let path = format!(
-            "/v1/accounts/{}/balance?instType=BROKERAGE&realTimeNAV=true",
-            account_id_key
+            "/v1/accounts/{}/transactions?{}",
+            account_id,
+            query.to_query_string()
         );
 
-        let balance = match self.api_get::<BalanceResponse>(&path) {
-            Ok(resp) => {
-                let rt = resp.response.computed.and_then(|c| c.real_time);
-
-                AccountBalance {
-                    currency: "USD".to_string(),
-                    total_equity: rt
-                        .as_ref()
-                        .and_then(|r| r.total_account_value)
-                        .unwrap_or(0.0),
-                    available_cash: rt.as_ref().and_then(|r| r.net_mv).unwrap_or(0.0),
-                    buying_power: rt.as_ref().and_then(|r| r.total_long_value).unwrap_or(0.0),
-                    locked_cash: 0.0,
-                }
+        // E*TRADE answers 204 No Content when the range has no transactions
+        let resp: TransactionListResponse = match self.api_get_optional(&path)? {
+            Some(r) => r,
+            None => {
+                return Ok(TransactionPage {
+                    transactions: vec![],
+                    next_marker: None,
+                    more: false,
+                    total_count: Some(0),
+                })
             }
-            Err(_) => AccountBalance {
-                currency: "USD".to_string(),
-                total_equity: 0.0,
-                available_cash: 0.0,
-                buying_power: 0.0,
-                locked_cash: 0.0,
-            },
         };
 
-        // Get positions
-        let positions = self.get_positions(account_id_key).unwrap_or_default();
+        let inner = match resp.response {
+            Some(i) => i,
+            None => {
+                return Ok(TransactionPage {
+                    transactions: vec![],
+                    next_marker: None,
+                    more: false,
+                    total_count: Some(0),
+                })
+            }
+        };
 
-        Ok((balance, positions))
+        let more = inner.more_transactions.unwrap_or(false);
+
+        Ok(TransactionPage {
+            transactions: inner
+                .transaction
+                .unwrap_or_default()
+                .into_iter()
+                .map(Transaction::from)
+                .collect(),
+            next_marker: if more { inner.marker } else { None },
+            more,
+            total_count: inner.total_count,
+        })
     }
 
-    /// Get positions for an account
-    pub fn get_positions(&self, account_id: &str) -> Result<Vec<Position>, String> {
+    /// List all transactions in the query range, following page markers
+    pub fn list_all_transactions(
+        &self,
+        account_id: &str,
+        query: &TransactionQuery,
+    ) -> Result<Vec<Transaction>, PluginError> {
+        transactions::collect_pages(query, |q| self.list_transactions(account_id, q))
+    }
+
+    /// Get details for a single transaction
+    pub fn get_transaction(
+        &self,
+        account_id: &str,
+        transaction_id: &str,
+    ) -> Result<Transaction, PluginError> {
+        #[derive(Deserialize)]
+        struct TransactionDetailsResponse {
+            #[serde(rename = "TransactionDetailsResponse")]
+            response: ETradeTransaction,
+        }
+
+        let path = format!(
+            "/v1/accounts/{}/transactions/{}",
+            account_id,
+            percent_encode(transaction_id)
+        );
+        let resp: TransactionDetailsResponse = self.api_get(&path)?;
+
+        Ok(Transaction::from(resp.response))
+    }
+
+    /// Look up products by company name or partial symbol
+    pub fn lookup_symbol(&self, search: &str) -> Result<Vec<SymbolLookupResult>, PluginError> {
         #[derive(Deserialize)]
-        struct PortfolioResponse {
-            #[serde(rename = "PortfolioResponse")]
-            response: Option<PortfolioInner>,
+        struct LookupResponse {
+            #[serde(rename = "LookupResponse")]
+            response: LookupInner,
         }
 
         #[derive(Deserialize)]
-        struct PortfolioInner {
-            #[serde(rename = "AccountPortfolio")]
-            account_portfolio: Option<Vec<AccountPortfolio>>,
+        struct LookupInner {
+            #[serde(rename = "Data")]
+            data: Option<Vec<SymbolLookupResult>>,
+        }
+
+        let search = search.trim();
+        if search.is_empty() {
+            return Err(PluginError::validation("Search term must not be empty"));
         }
 
+        let path = format!("/v1/market/lookup/{}", percent_encode(search));
+        let resp: LookupResponse = self.api_get(&path)?;
+
+        Ok(resp.response.data.unwrap_or_default())
+    }
+
+    /// Get quotes for up to `MAX_QUOTE_SYMBOLS` symbols
+    pub fn get_quotes(
+        &self,
+        symbols: &[String],
+        detail: QuoteDetail,
+    ) -> Result<Vec<Quote>, PluginError> {
         #[derive(Deserialize)]
-        struct AccountPortfolio {
-            #[serde(rename = "Position")]
-            position: Option<Vec<ETradePosition>>,
+        struct QuoteResponse {
+            #[serde(rename = "QuoteResponse")]
+            response: QuoteInner,
         }
 
         #[derive(Deserialize)]
-        struct ETradePosition {
-            #[serde(rename = "Product")]
-            product: ProductInfo,
-            quantity: Option<f64>,
-            #[serde(rename = "costPerShare")]
-            cost_per_share: Option<f64>,
-            #[serde(rename = "marketValue")]
-            market_value: Option<f64>,
-            #[serde(rename = "totalGain")]
-            total_gain: Option<f64>,
-            #[serde(rename = "totalGainPct")]
-            total_gain_pct: Option<f64>,
-            #[serde(rename = "Quick")]
-            quick: Option<QuickView>,
+        struct QuoteInner {
+            #[serde(rename = "QuoteData")]
+            quote_data: Option<Vec<QuoteData>>,
         }
 
         #[derive(Deserialize)]
-        struct ProductInfo {
-            symbol: String,
+        struct QuoteData {
+            #[serde(rename = "Product")]
+            product: QuoteProduct,
+            #[serde(rename = "quoteStatus")]
+            quote_status: Option<String>,
+            #[serde(rename = "dateTimeUTC")]
+            date_time_utc: Option<i64>,
+            #[serde(flatten)]
+            blocks: HashMap<String, serde_json::Value>,
         }
 
         #[derive(Deserialize)]
-        struct QuickView {
-            #[serde(rename = "lastTrade")]
-            last_trade: Option<f64>,
+        struct QuoteProduct {
+            symbol: String,
         }
 
-        let path = format!("/v1/accounts/{}/portfolio", account_id);
-        let resp: PortfolioResponse = self.api_get(&path)?;
+        if symbols.is_empty() {
+            return Ok(vec![]);
+        }
+        if symbols.len() > MAX_QUOTE_SYMBOLS {
+            return Err(PluginError::validation(format!(
+                "Too many symbols: {} (max {})",
+                symbols.len(),
+                MAX_QUOTE_SYMBOLS
+            )));
+        }
 
-        let mut positions = Vec::new();
+        let joined = symbols
+            .iter()
+            .map(|s| percent_encode(s))
+            .collect::<Vec<_>>()
+            .join(",");
+        let path = format!("/v1/market/quote/{}?detailFlag={}", joined, detail.as_str());
+        let resp: QuoteResponse = self.api_get(&path)?;
 
-        if let Some(inner) = resp.response {
-            if let Some(portfolios) = inner.account_portfolio {
-                for portfolio in portfolios {
-                    if let Some(pos_list) = portfolio.position {
-                        for pos in pos_list {
-                            let current_price = pos
-                                .quick
-                                .and_then(|q| q.last_trade)
-                                .or(pos.cost_per_share)
-                                .unwrap_or(0.0);
-
-                            positions.push(Position {
-                                symbol_id: pos.product.symbol,
-                                quantity: pos.quantity.unwrap_or(0.0),
-                                average_price: pos.cost_per_share.unwrap_or(0.0),
-                                current_price,
-                                unrealized_pnl: pos.total_gain.unwrap_or(0.0),
-                                unrealized_pnl_percent: pos.total_gain_pct.unwrap_or(0.0),
-                            });
-                        }
-                    }
+        let quotes = resp
+            .response
+            .quote_data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|q| {
+                let data = [
+                    "All",
+                    "Intraday",
+                    "Fundamental",
+                    "Week52",
+                    "Option",
+                    "MutualFund",
+                ]
+                .iter()
+                .find_map(|k| q.blocks.get(*k).cloned());
+                let field = |name: &str| {
+                    data.as_ref()
+                        .and_then(|d| d.get(name))
+                        .and_then(|v| v.as_f64())
+                };
+
+                Quote {
+                    symbol: q.product.symbol,
+                    detail,
+                    last_price: field("lastTrade").or_else(|| field("netAssetValue")),
+                    bid: field("bid"),
+                    ask: field("ask"),
+                    change: field("changeClose"),
+                    volume: field("totalVolume"),
+                    quote_status: q.quote_status,
+                    quoted_at: q.date_time_utc,
+                    data,
                 }
-            }
-        }
+            })
+            .collect();
 
-        Ok(positions)
+        Ok(quotes)
     }
 
     /// Submit an order
-    pub fn submit_order(&self, account_id: &str, order: &OrderRequest) -> Result<Order, String> {
+    pub fn submit_order(
+        &self,
+        account_id: &str,
+        order: &OrderRequest,
+    ) -> Result<Order, PluginError> {
         #[derive(serde::Serialize)]
         struct PlaceOrderRequest {
             #[serde(rename = "PlaceOrderRequest")]
@@ -525,20 +1094,266 @@ impl ETradeClient {
             persona_id: order.persona_id.clone(),
         })
     }
+
+    /// Cancel an open order by its E*TRADE order ID
+    pub fn cancel_order(
+        &self,
+        account_id: &str,
+        order_id: &str,
+    ) -> Result<CancelledOrder, PluginError> {
+        let order_id: i64 = order_id
+            .trim()
+            .parse()
+            .map_err(|_| PluginError::validation(format!("Invalid order ID: {}", order_id)))?;
+
+        #[derive(Serialize)]
+        struct CancelOrderRequest {
+            #[serde(rename = "CancelOrderRequest")]
+            request: CancelOrderInner,
+        }
+
+        #[derive(Serialize)]
+        struct CancelOrderInner {
+            #[serde(rename = "orderId")]
+            order_id: i64,
+        }
+
+        #[derive(Deserialize)]
+        struct CancelOrderResponse {
+            #[serde(rename = "CancelOrderResponse")]
+            response: CancelOrderResult,
+        }
+
+        #[derive(Deserialize)]
+        struct CancelOrderResult {
+            #[serde(rename = "cancelTime")]
+            cancel_time: Option<i64>,
+            #[serde(rename = "Messages")]
+            messages: Option<CancelMessages>,
+        }
+
+        #[derive(Deserialize)]
+        struct CancelMessages {
+            #[serde(rename = "Message", default)]
+            message: Vec<CancelMessage>,
+        }
+
+        #[derive(Deserialize)]
+        struct CancelMessage {
+            description: Option<String>,
+        }
+
+        let path = format!("/v1/accounts/{}/orders/cancel", account_id);
+        let req = CancelOrderRequest {
+            request: CancelOrderInner { order_id },
+        };
+        let resp: CancelOrderResponse = self.api_put(&path, &req)?;
+        let messages = resp
+            .response
+            .messages
+            .map(|m| m.message)
+            .unwrap_or_default();
+
+        Ok(CancelledOrder {
+            order_id: order_id.to_string(),
+            cancelled_at: resp.response.cancel_time.and_then(millis_to_utc),
+            messages: messages.into_iter().filter_map(|m| m.description).collect(),
+        })
+    }
 }
 
-/// URL percent encoding (RFC 3986)
-fn percent_encode(s: &str) -> String {
-    let mut result = String::new();
-    for byte in s.bytes() {
-        match byte {
-            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
-                result.push(byte as char);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, respond_with};
+
+    /// Sandbox client whose retries do not sleep
+    fn client(config: serde_json::Value) -> ETradeClient {
+        ETradeClient::new(
+            "ck".to_string(),
+            "cs".to_string(),
+            "token".to_string(),
+            "secret".to_string(),
+            true,
+        )
+        .retry_policy(RetryPolicy {
+            sleep: |_| {},
+            ..RetryPolicy::from_config(&config)
+        })
+        .timeouts(Timeouts::from_config(&config))
+        .tracer(Tracer::from_config(&config))
+    }
+
+    /// Value of one parameter of an `OAuth k="v", ...` header
+    fn oauth_param(header: &str, name: &str) -> String {
+        header
+            .trim_start_matches("OAuth ")
+            .split(", ")
+            .find_map(|p| p.strip_prefix(&format!("{}=\"", name)))
+            .map(|v| v.trim_end_matches('"').to_string())
+            .unwrap_or_default()
+    }
+
+    /// Check the method and URL of a sent request, and that its
+    /// Authorization header is the signature of exactly that method and URL
+    fn assert_signed(request: &HttpRequest, method: &str, path: &str) {
+        assert_eq!(request.method.as_str(), method);
+        assert_eq!(request.url, format!("{}{}", SANDBOX_URL, path));
+
+        let header = &request.headers["Authorization"];
+        let signer = OAuthSigner::new(
+            "ck".to_string(),
+            "cs".to_string(),
+            "token".to_string(),
+            "secret".to_string(),
+        );
+        let expected = signer.authorization(
+            method,
+            &request.url,
+            oauth_param(header, "oauth_timestamp"),
+            oauth_param(header, "oauth_nonce"),
+        );
+        assert_eq!(header, &expected, "{} {}", method, path);
+    }
+
+    #[test]
+    fn helpers_send_signed_requests_with_their_method() {
+        let client = client(serde_json::json!({}));
+        let body = serde_json::json!({"key": "value"});
+
+        client
+            .api_get::<serde_json::Value>("/v1/user/alerts?count=5")
+            .unwrap();
+        client
+            .api_post::<serde_json::Value, _>("/v1/accounts/k/orders/preview", &body)
+            .unwrap();
+        client
+            .api_put::<serde_json::Value, _>("/v1/accounts/k/orders/cancel", &body)
+            .unwrap();
+        client
+            .api_patch::<serde_json::Value, _>("/v1/user/alerts/1", &body)
+            .unwrap();
+        client
+            .api_delete::<serde_json::Value>("/v1/user/alerts/1")
+            .unwrap();
+
+        let sent = test_support::take_sent();
+        let expected = [
+            ("GET", "/v1/user/alerts?count=5"),
+            ("POST", "/v1/accounts/k/orders/preview"),
+            ("PUT", "/v1/accounts/k/orders/cancel"),
+            ("PATCH", "/v1/user/alerts/1"),
+            ("DELETE", "/v1/user/alerts/1"),
+        ];
+        assert_eq!(sent.len(), expected.len());
+        for (request, (method, path)) in sent.iter().zip(expected) {
+            assert_signed(request, method, path);
+            assert_eq!(request.headers["Accept"], "application/json");
+            if method == "GET" || method == "DELETE" {
+                assert_eq!(request.body, None);
+            } else {
+                assert_eq!(request.headers["Content-Type"], "application/json");
+                assert_eq!(request.body.as_deref(), Some(r#"{"key":"value"}"#));
+            }
+        }
+    }
+
+    #[test]
+    fn cancels_orders_with_a_signed_put() {
+        let client = client(serde_json::json!({}));
+        respond_with(|_| {
+            test_support::response(
+                200,
+                "application/json",
+                r#"{"CancelOrderResponse": {
+                    "accountId": "12345678",
+                    "orderId": 42,
+                    "cancelTime": 1700000000000,
+                    "Messages": {"Message": [{
+                        "code": 5011,
+                        "description": "Your request to cancel your order is being processed.",
+                        "type": "WARNING"
+                    }]}
+                }}"#,
+            )
+        });
+
+        let cancelled = client.cancel_order("key", "42").unwrap();
+        assert_eq!(cancelled.order_id, "42");
+        assert_eq!(
+            cancelled.cancelled_at.map(|t| t.timestamp_millis()),
+            Some(1700000000000)
+        );
+        assert_eq!(cancelled.messages.len(), 1);
+
+        let sent = test_support::take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_signed(&sent[0], "PUT", "/v1/accounts/key/orders/cancel");
+        assert_eq!(
+            sent[0].body.as_deref(),
+            Some(r#"{"CancelOrderRequest":{"orderId":42}}"#)
+        );
+    }
+
+    #[test]
+    fn rejects_non_numeric_order_ids_without_sending() {
+        let client = client(serde_json::json!({}));
+        match client.cancel_order("key", "abc") {
+            Err(PluginError::Validation { .. }) => {}
+            other => panic!("expected validation error, got {:?}", other),
+        }
+        assert!(test_support::take_sent().is_empty());
+    }
+
+    #[test]
+    fn host_timeouts_map_to_timeout_errors() {
+        let client = client(serde_json::json!({
+            "timeout_market_ms": 5000,
+            "retry_max_attempts": 2
+        }));
+        respond_with(|_| HttpResponse::local_error("Request timed out".to_string()));
+
+        match client.lookup_symbol("apple") {
+            Err(PluginError::Timeout {
+                operation,
+                timeout_ms,
+                ..
+            }) => {
+                assert_eq!(operation, Operation::Market);
+                assert_eq!(timeout_ms, 5000);
             }
-            _ => {
-                result.push_str(&format!("%{:02X}", byte));
+            other => panic!("expected timeout, got {:?}", other),
+        }
+
+        let sent = test_support::take_sent();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|r| r.timeout_ms == 5000));
+
+        let traces = client.traces(10);
+        assert_eq!(traces.len(), 2);
+        for entry in traces {
+            let error = entry.error.unwrap_or_default();
+            assert!(error.starts_with("Timed out after 5000ms"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn request_timeout_status_maps_to_timeout_errors() {
+        let client = client(serde_json::json!({"timeout_auth_ms": 2000}));
+        respond_with(|_| test_support::response(408, "text/plain", ""));
+
+        match client.renew_access_token() {
+            Err(PluginError::Timeout {
+                operation,
+                timeout_ms,
+                ..
+            }) => {
+                assert_eq!(operation, Operation::Auth);
+                assert_eq!(timeout_ms, 2000);
             }
+            other => panic!("expected timeout, got {:?}", other),
         }
+        let error = client.traces(1)[0].error.clone().unwrap_or_default();
+        assert!(error.starts_with("Timed out"), "{}", error);
     }
-    result
 }
@@ -2,6 +2,7 @@
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
+#[cfg(not(test))]
 use std::slice;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +15,18 @@ pub enum HttpMethod {
     Patch,
 }
 
+impl HttpMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Patch => "PATCH",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpRequest {
     pub method: HttpMethod,
@@ -30,6 +43,31 @@ fn default_timeout() -> u32 {
     30000
 }
 
+/// Body format of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    Json,
+    Xml,
+    Html,
+    /// `application/x-www-form-urlencoded`, used by the OAuth endpoints
+    Form,
+    Text,
+    Empty,
+}
+
+impl ContentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentKind::Json => "JSON",
+            ContentKind::Xml => "XML",
+            ContentKind::Html => "HTML",
+            ContentKind::Form => "form-encoded",
+            ContentKind::Text => "text",
+            ContentKind::Empty => "empty",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpResponse {
     pub status: u16,
@@ -39,21 +77,113 @@ pub struct HttpResponse {
 }
 
 impl HttpResponse {
+    /// Response for a request that failed before or after reaching the host
+    pub fn local_error(error: String) -> Self {
+        Self {
+            status: 0,
+            headers: HashMap::new(),
+            body: String::new(),
+            error: Some(error),
+        }
+    }
+
+    /// Case-insensitive header lookup
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// `Retry-After` in seconds (the HTTP-date form is not used by E*TRADE)
+    pub fn retry_after_secs(&self) -> Option<u64> {
+        self.header("Retry-After")
+            .and_then(|v| v.trim().parse().ok())
+    }
+
+    /// Host-reported timeout or 408 Request Timeout
+    pub fn is_timeout(&self) -> bool {
+        self.status == 408
+            || self
+                .error
+                .as_deref()
+                .map(|e| {
+                    let e = e.to_lowercase();
+                    e.contains("timeout") || e.contains("timed out")
+                })
+                .unwrap_or(false)
+    }
+
     pub fn is_success(&self) -> bool {
         self.error.is_none() && (200..300).contains(&self.status)
     }
 
+    /// Body format from `Content-Type`, or sniffed from the body when the
+    /// header is missing or generic
+    pub fn content_kind(&self) -> ContentKind {
+        let body = self.body.trim_start();
+        if body.is_empty() {
+            return ContentKind::Empty;
+        }
+
+        let content_type = self
+            .header("Content-Type")
+            .map(|v| v.to_ascii_lowercase())
+            .unwrap_or_default();
+        if content_type.contains("json") {
+            return ContentKind::Json;
+        }
+        if content_type.contains("html") {
+            return ContentKind::Html;
+        }
+        if content_type.contains("xml") {
+            return ContentKind::Xml;
+        }
+        if content_type.contains("x-www-form-urlencoded") {
+            return ContentKind::Form;
+        }
+
+        let lower = body.get(..15).unwrap_or(body).to_ascii_lowercase();
+        if body.starts_with('{') || body.starts_with('[') {
+            ContentKind::Json
+        } else if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+            ContentKind::Html
+        } else if body.starts_with('<') {
+            ContentKind::Xml
+        } else if body.contains('=') && !body.trim_end().contains(char::is_whitespace) {
+            ContentKind::Form
+        } else {
+            ContentKind::Text
+        }
+    }
+
     pub fn json<T: DeserializeOwned>(&self) -> Result<T, String> {
-        serde_json::from_str(&self.body).map_err(|e| format!("JSON parse error: {}", e))
+        match self.content_kind() {
+            ContentKind::Json | ContentKind::Text | ContentKind::Empty => {
+                serde_json::from_str(&self.body).map_err(|e| format!("JSON parse error: {}", e))
+            }
+            kind => Err(format!(
+                "Expected a JSON response but received {} ({})",
+                kind.as_str(),
+                self.header("Content-Type").unwrap_or("no content type")
+            )),
+        }
     }
 }
 
+#[cfg(not(test))]
 extern "C" {
     fn http_request(req_ptr: i32, req_len: i32) -> u64;
 }
 
+#[cfg(not(test))]
 pub fn execute(request: HttpRequest) -> HttpResponse {
-    let req_bytes = serde_json::to_vec(&request).expect("Failed to serialize request");
+    let req_bytes = match serde_json::to_vec(&request) {
+        Ok(b) => b,
+        Err(e) => {
+            return HttpResponse::local_error(format!("Failed to serialize request: {}", e));
+        }
+    };
     let req_ptr = req_bytes.as_ptr() as i32;
     let req_len = req_bytes.len() as i32;
 
@@ -62,29 +192,39 @@ pub fn execute(request: HttpRequest) -> HttpResponse {
     let res_ptr = (result_packed >> 32) as i32;
     let res_len = (result_packed & 0xFFFFFFFF) as i32;
 
-    if res_ptr == 0 || res_len == 0 {
-        return HttpResponse {
-            status: 0,
-            headers: HashMap::new(),
-            body: String::new(),
-            error: Some("Host function returned null response".to_string()),
-        };
+    if res_ptr == 0 || res_len <= 0 {
+        return HttpResponse::local_error("Host function returned null response".to_string());
     }
 
     let res_slice = unsafe { slice::from_raw_parts(res_ptr as *const u8, res_len as usize) };
 
-    serde_json::from_slice(res_slice).unwrap_or_else(|e| HttpResponse {
-        status: 0,
-        headers: HashMap::new(),
-        body: String::new(),
-        error: Some(format!("Failed to parse response: {}", e)),
-    })
+    let response = serde_json::from_slice(res_slice)
+        .unwrap_or_else(|e| HttpResponse::local_error(format!("Failed to parse response: {}", e)));
+
+    // This plugin's assumption, not a published host contract (see README
+    // "메모리 관리"): the host writes the response into a buffer from this
+    // plugin's exported `alloc` and hands it over without freeing it. A host
+    // that frees the buffer itself would double-free it here, and one that
+    // uses its own allocator would make this `dealloc` undefined behaviour.
+    // `req_bytes` stays ours and is dropped on return.
+    crate::dealloc(res_ptr, res_len);
+
+    response
+}
+
+#[cfg(test)]
+pub use crate::test_support::execute;
+
+/// Adds headers derived from the final request, such as an OAuth
+/// signature. Runs on every send, so retries are signed afresh.
+pub trait RequestSigner {
+    fn sign(&self, request: &mut HttpRequest);
 }
 
 pub struct HttpClient {
     base_url: String,
     default_headers: HashMap<String, String>,
-    timeout_ms: u32,
+    signer: Option<Box<dyn RequestSigner + Send>>,
 }
 
 impl HttpClient {
@@ -92,7 +232,7 @@ impl HttpClient {
         Self {
             base_url: base_url.to_string(),
             default_headers: HashMap::new(),
-            timeout_ms: 30000,
+            signer: None,
         }
     }
 
@@ -102,6 +242,11 @@ impl HttpClient {
         self
     }
 
+    pub fn signer<S: RequestSigner + Send + 'static>(mut self, signer: S) -> Self {
+        self.signer = Some(Box::new(signer));
+        self
+    }
+
     pub fn set_header(&mut self, key: &str, value: &str) {
         self.default_headers
             .insert(key.to_string(), value.to_string());
@@ -123,47 +268,14 @@ impl HttpClient {
         headers
     }
 
-    pub fn get(&self, path: &str, headers: Option<HashMap<String, String>>) -> HttpResponse {
-        execute(HttpRequest {
-            method: HttpMethod::Get,
-            url: self.build_url(path),
-            headers: self.merge_headers(headers),
-            body: None,
-            timeout_ms: self.timeout_ms,
-        })
-    }
-
-    pub fn post(
-        &self,
-        path: &str,
-        body: Option<String>,
-        headers: Option<HashMap<String, String>>,
-    ) -> HttpResponse {
-        execute(HttpRequest {
-            method: HttpMethod::Post,
-            url: self.build_url(path),
-            headers: self.merge_headers(headers),
-            body,
-            timeout_ms: self.timeout_ms,
-        })
-    }
-
-    pub fn post_json<T: Serialize>(
-        &self,
-        path: &str,
-        body: &T,
-        headers: Option<HashMap<String, String>>,
-    ) -> HttpResponse {
-        let body_str = serde_json::to_string(body).expect("Failed to serialize body");
-        let mut h = self.merge_headers(headers);
-        h.entry("Content-Type".to_string())
-            .or_insert_with(|| "application/json".to_string());
-        execute(HttpRequest {
-            method: HttpMethod::Post,
-            url: self.build_url(path),
-            headers: h,
-            body: Some(body_str),
-            timeout_ms: self.timeout_ms,
-        })
+    /// Resolve the URL against the base URL, apply default headers, sign
+    /// and execute. Headers already on the request take precedence.
+    pub fn send(&self, mut request: HttpRequest) -> HttpResponse {
+        request.url = self.build_url(&request.url);
+        request.headers = self.merge_headers(Some(request.headers));
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut request);
+        }
+        execute(request)
     }
 }
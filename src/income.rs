@@ -0,0 +1,277 @@
+//! Dividend and interest income report
+//!
+//! Summarizes income transactions per symbol and per calendar month so the
+//! host can attribute income to personas and compute yield-on-cost.
+
+use crate::transactions::{Transaction, TransactionType};
+use chrono::NaiveDate;
+use models::portfolio::Position;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IncomeBreakdown {
+    pub qualified_dividends: f64,
+    pub ordinary_dividends: f64,
+    pub interest: f64,
+    /// Amount of dividends used to buy more shares (already counted as income)
+    pub reinvested: f64,
+    /// Tax withheld, reported as a positive amount
+    pub withholding: f64,
+    /// Dividends + interest - withholding
+    pub net_income: f64,
+}
+
+impl IncomeBreakdown {
+    fn add(&mut self, tx: &Transaction) -> bool {
+        match tx.transaction_type {
+            TransactionType::QualifiedDividend => self.qualified_dividends += tx.amount,
+            TransactionType::Dividend => self.ordinary_dividends += tx.amount,
+            TransactionType::Interest => self.interest += tx.amount,
+            TransactionType::Reinvestment => self.reinvested += tx.amount.abs(),
+            TransactionType::Withholding => self.withholding += -tx.amount,
+            _ => return false,
+        }
+        self.net_income =
+            self.qualified_dividends + self.ordinary_dividends + self.interest - self.withholding;
+        true
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MonthlyIncome {
+    /// `None` for account-level income such as cash sweep interest
+    pub symbol: Option<String>,
+    /// Calendar month as `YYYY-MM`
+    pub month: String,
+    #[serde(flatten)]
+    pub income: IncomeBreakdown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolIncome {
+    pub symbol: Option<String>,
+    #[serde(flatten)]
+    pub income: IncomeBreakdown,
+    /// Cost basis of the currently held position, if any
+    pub cost_basis: Option<f64>,
+    /// Net income over the report period divided by cost basis, in percent
+    pub yield_on_cost_pct: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IncomeReport {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub monthly: Vec<MonthlyIncome>,
+    pub by_symbol: Vec<SymbolIncome>,
+    pub totals: IncomeBreakdown,
+}
+
+/// Build an income report from transactions and the current positions.
+/// Transactions dated outside `start_date..=end_date` are skipped.
+pub fn build_income_report(
+    transactions: &[Transaction],
+    positions: &[Position],
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+) -> IncomeReport {
+    let mut monthly: BTreeMap<(Option<String>, String), IncomeBreakdown> = BTreeMap::new();
+    let mut by_symbol: BTreeMap<Option<String>, IncomeBreakdown> = BTreeMap::new();
+    let mut totals = IncomeBreakdown::default();
+
+    for tx in transactions {
+        let date = match tx.transaction_date.or(tx.post_date) {
+            Some(d) => d,
+            None => continue,
+        };
+        let day = date.date_naive();
+        if start_date.is_some_and(|s| day < s) || end_date.is_some_and(|e| day > e) {
+            continue;
+        }
+        let month = date.format("%Y-%m").to_string();
+
+        if !totals.add(tx) {
+            continue;
+        }
+        monthly
+            .entry((tx.symbol.clone(), month))
+            .or_default()
+            .add(tx);
+        by_symbol.entry(tx.symbol.clone()).or_default().add(tx);
+    }
+
+    let by_symbol = by_symbol
+        .into_iter()
+        .map(|(symbol, income)| {
+            let cost_basis = symbol.as_ref().and_then(|s| {
+                positions
+                    .iter()
+                    .find(|p| p.symbol_id.eq_ignore_ascii_case(s))
+                    .map(|p| (p.average_price * p.quantity).abs())
+                    .filter(|c| *c > 0.0)
+            });
+            let yield_on_cost_pct = cost_basis.map(|c| income.net_income / c * 100.0);
+
+            SymbolIncome {
+                symbol,
+                income,
+                cost_basis,
+                yield_on_cost_pct,
+            }
+        })
+        .collect();
+
+    IncomeReport {
+        start_date,
+        end_date,
+        monthly: monthly
+            .into_iter()
+            .map(|((symbol, month), income)| MonthlyIncome {
+                symbol,
+                month,
+                income,
+            })
+            .collect(),
+        by_symbol,
+        totals,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn tx(
+        transaction_type: TransactionType,
+        symbol: Option<&str>,
+        date: (i32, u32, u32),
+        amount: f64,
+    ) -> Transaction {
+        Transaction {
+            id: String::new(),
+            account_id: "1".to_string(),
+            transaction_type,
+            raw_type: String::new(),
+            transaction_date: Utc
+                .with_ymd_and_hms(date.0, date.1, date.2, 0, 0, 0)
+                .single(),
+            post_date: None,
+            amount,
+            description: String::new(),
+            symbol: symbol.map(|s| s.to_string()),
+            security_type: None,
+            quantity: None,
+            price: None,
+            fee: None,
+            memo: None,
+        }
+    }
+
+    fn position(symbol: &str, quantity: f64, average_price: f64) -> Position {
+        Position {
+            symbol_id: symbol.to_string(),
+            quantity,
+            average_price,
+            current_price: average_price,
+            unrealized_pnl: 0.0,
+            unrealized_pnl_percent: 0.0,
+        }
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn classifies_dividends_interest_and_withholding() {
+        let transactions = vec![
+            tx(
+                TransactionType::QualifiedDividend,
+                Some("AAPL"),
+                (2024, 2, 15),
+                24.0,
+            ),
+            tx(TransactionType::Dividend, Some("AAPL"), (2024, 5, 15), 6.0),
+            tx(
+                TransactionType::Withholding,
+                Some("AAPL"),
+                (2024, 5, 15),
+                -3.0,
+            ),
+            tx(
+                TransactionType::Reinvestment,
+                Some("AAPL"),
+                (2024, 5, 16),
+                -6.0,
+            ),
+            tx(TransactionType::Interest, None, (2024, 5, 31), 1.5),
+            tx(TransactionType::Buy, Some("AAPL"), (2024, 1, 2), -1000.0),
+        ];
+        let report =
+            build_income_report(&transactions, &[position("AAPL", 10.0, 100.0)], None, None);
+
+        let totals = &report.totals;
+        assert_eq!(totals.qualified_dividends, 24.0);
+        assert_eq!(totals.ordinary_dividends, 6.0);
+        assert_eq!(totals.interest, 1.5);
+        assert_eq!(totals.withholding, 3.0);
+        assert_eq!(totals.reinvested, 6.0);
+        assert_eq!(totals.net_income, 28.5);
+
+        let aapl = report
+            .by_symbol
+            .iter()
+            .find(|s| s.symbol.as_deref() == Some("AAPL"))
+            .unwrap();
+        assert_eq!(aapl.income.net_income, 27.0);
+        assert_eq!(aapl.cost_basis, Some(1000.0));
+        assert_eq!(aapl.yield_on_cost_pct, Some(2.7));
+
+        let cash = report
+            .by_symbol
+            .iter()
+            .find(|s| s.symbol.is_none())
+            .unwrap();
+        assert_eq!(cash.income.interest, 1.5);
+        assert_eq!(cash.cost_basis, None);
+
+        let months: Vec<(Option<&str>, &str)> = report
+            .monthly
+            .iter()
+            .map(|m| (m.symbol.as_deref(), m.month.as_str()))
+            .collect();
+        assert_eq!(
+            months,
+            [
+                (None, "2024-05"),
+                (Some("AAPL"), "2024-02"),
+                (Some("AAPL"), "2024-05")
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_transactions_outside_the_date_range() {
+        let transactions = vec![
+            tx(
+                TransactionType::Dividend,
+                Some("MSFT"),
+                (2023, 12, 31),
+                10.0,
+            ),
+            tx(TransactionType::Dividend, Some("MSFT"), (2024, 1, 1), 20.0),
+            tx(TransactionType::Dividend, Some("MSFT"), (2024, 6, 30), 30.0),
+            tx(TransactionType::Dividend, Some("MSFT"), (2024, 7, 1), 40.0),
+        ];
+        let report = build_income_report(&transactions, &[], date(2024, 1, 1), date(2024, 6, 30));
+
+        assert_eq!(report.totals.ordinary_dividends, 50.0);
+        assert_eq!(report.monthly.len(), 2);
+        assert_eq!(report.start_date, date(2024, 1, 1));
+
+        let open_ended = build_income_report(&transactions, &[], date(2024, 6, 1), None);
+        assert_eq!(open_ended.totals.ordinary_dividends, 70.0);
+    }
+}
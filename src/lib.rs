@@ -14,21 +14,366 @@
 // Allow dead_code for structs/fields prepared for future API integration
 #![allow(dead_code)]
 
+mod account_cache;
+mod aggregate;
+mod balance;
+mod circuit_breaker;
+mod common;
+mod error;
 mod etrade;
 mod http;
+mod income;
+mod oauth;
+mod portfolio;
+mod quote_cache;
+mod rate_limit;
+mod realized_pnl;
+mod retry;
+mod timeouts;
+mod trace;
+mod transactions;
+mod xml;
+
+#[cfg(test)]
+mod test_support;
 
 use chrono::Utc;
 use std::collections::HashMap;
 use std::slice;
 use std::sync::Mutex;
 
-use etrade::ETradeClient;
-use models::order::{Order, OrderStatus};
-use models::portfolio::{AccountBalance, AccountSummary};
-use plugin_api::{
-    GetAccountsRequest, GetAccountsResponse, GetPositionsRequest, GetPositionsResponse,
-    SubmitOrderRequest, SubmitOrderResponse,
+use account_cache::{AccountCache, AccountDirectory, DEFAULT_ACCOUNT_CACHE_TTL_MS};
+use aggregate::AggregatePortfolio;
+use chrono::NaiveDate;
+use circuit_breaker::{CircuitBreaker, CircuitStatus};
+use error::{ErrorEnvelope, PluginError};
+use etrade::{
+    AccountInfo, AccountSnapshot, AccountWarning, CancelledOrder, ETradeClient, Quote, QuoteDetail,
+    SymbolLookupResult,
 };
+use income::IncomeReport;
+use models::order::Order;
+use models::portfolio::{AccountSummary, Position};
+use plugin_api::{GetPositionsRequest, SubmitOrderRequest};
+use portfolio::{AssetClass, PortfolioQuery, PositionDetail, PositionLot};
+use quote_cache::{QuoteCache, DEFAULT_QUOTE_TTL_MS};
+use rate_limit::{RateLimitStatus, RateLimiter};
+use realized_pnl::{LotMethod, PeriodGranularity, RealizedPnlReport, SpecificLotSelection};
+use retry::RetryPolicy;
+use serde::{Deserialize, Serialize};
+use timeouts::Timeouts;
+use trace::{LogLevel, TraceEntry, Tracer};
+use transactions::{SortOrder, Transaction, TransactionQuery, MAX_HISTORY_DAYS};
+
+// --- Plugin-specific Request/Response Types ---
+
+/// `get_accounts` options; all optional so a plain `GetAccountsRequest` still parses
+#[derive(Debug, Default, Deserialize)]
+struct AccountsRequest {
+    /// Skip balance and positions requests; return account metadata only
+    #[serde(default)]
+    accounts_only: bool,
+    /// Maximum snapshot age to accept from the cache (defaults to the TTL)
+    #[serde(default)]
+    max_age_ms: Option<i64>,
+    #[serde(default)]
+    force_refresh: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetAccountRequest {
+    account_id: String,
+    #[serde(default)]
+    max_age_ms: Option<i64>,
+    #[serde(default)]
+    force_refresh: bool,
+}
+
+#[derive(Serialize)]
+struct GetAccountResponse {
+    account: Option<AccountSummary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<AccountWarning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+/// `GetAccountsResponse` plus per-account partial failures
+#[derive(Serialize)]
+struct AccountsResponse {
+    accounts: Vec<AccountSummary>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<AccountWarning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+impl AccountsResponse {
+    fn error(error: PluginError) -> Self {
+        Self {
+            accounts: vec![],
+            warnings: vec![],
+            error: Some(error),
+        }
+    }
+}
+
+/// `SubmitOrderResponse` with the order left out when submission fails
+#[derive(Serialize)]
+struct SubmitOrderResponse {
+    order: Option<Order>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+impl SubmitOrderResponse {
+    fn error(error: PluginError) -> Self {
+        Self {
+            order: None,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelOrderRequest {
+    account_id: String,
+    order_id: String,
+}
+
+#[derive(Serialize)]
+struct CancelOrderResponse {
+    order: Option<CancelledOrder>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+/// `GetPositionsRequest` plus plugin-specific options
+#[derive(Deserialize)]
+struct PositionsRequest {
+    #[serde(flatten)]
+    base: GetPositionsRequest,
+    /// Embed tax lots in each position's extensions
+    #[serde(default)]
+    include_lots: bool,
+    /// Portfolio view and sort order
+    #[serde(flatten)]
+    query: PortfolioQuery,
+}
+
+/// `GetPositionsResponse` plus E*TRADE-specific fields keyed by position ID
+/// (or `symbol_id` when E*TRADE omits it)
+#[derive(Serialize)]
+struct PositionsResponse {
+    positions: Vec<Position>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    extensions: HashMap<String, HashMap<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+impl PositionsResponse {
+    fn error(error: PluginError) -> Self {
+        Self {
+            positions: vec![],
+            extensions: HashMap::new(),
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GetPositionLotsRequest {
+    account_id: String,
+    #[serde(default)]
+    symbol: Option<String>,
+    #[serde(default)]
+    position_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PositionLots {
+    symbol: String,
+    position_id: Option<String>,
+    lots: Vec<PositionLot>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetPositionLotsResponse {
+    positions: Vec<PositionLots>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LookupSymbolRequest {
+    search: String,
+}
+
+#[derive(Debug, Serialize)]
+struct LookupSymbolResponse {
+    results: Vec<SymbolLookupResult>,
+    /// True when one of the results matches the search term exactly,
+    /// i.e. the term is already a valid `symbol_id`
+    exact_match: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetQuotesRequest {
+    symbols: Vec<String>,
+    #[serde(default = "default_quote_detail")]
+    detail: QuoteDetail,
+    #[serde(default)]
+    force_refresh: bool,
+}
+
+fn default_quote_detail() -> QuoteDetail {
+    QuoteDetail::Intraday
+}
+
+#[derive(Debug, Serialize)]
+struct GetQuotesResponse {
+    quotes: Vec<Quote>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetTransactionsRequest {
+    account_id: String,
+    #[serde(flatten)]
+    query: TransactionQuery,
+    /// Follow page markers and return the whole range in one response
+    #[serde(default)]
+    fetch_all: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct GetTransactionsResponse {
+    transactions: Vec<Transaction>,
+    next_marker: Option<String>,
+    more: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_count: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetTransactionDetailRequest {
+    account_id: String,
+    transaction_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct GetTransactionDetailResponse {
+    transaction: Option<Transaction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetIncomeReportRequest {
+    account_id: String,
+    #[serde(default)]
+    start_date: Option<NaiveDate>,
+    #[serde(default)]
+    end_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetIncomeReportResponse {
+    report: Option<IncomeReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetRealizedPnlRequest {
+    account_id: String,
+    #[serde(default)]
+    start_date: Option<NaiveDate>,
+    #[serde(default)]
+    end_date: Option<NaiveDate>,
+    /// Earliest date to load buys from when building open lots
+    /// (defaults to the full history E*TRADE serves)
+    #[serde(default)]
+    history_start_date: Option<NaiveDate>,
+    #[serde(default)]
+    method: LotMethod,
+    #[serde(default)]
+    period: PeriodGranularity,
+    #[serde(default)]
+    specific_lots: Vec<SpecificLotSelection>,
+}
+
+#[derive(Debug, Serialize)]
+struct GetRealizedPnlResponse {
+    report: Option<RealizedPnlReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GetAggregatePortfolioRequest {
+    /// Accounts to include (`accountId` or `accountIdKey`); all when empty
+    #[serde(default)]
+    account_ids: Vec<String>,
+    /// Maximum snapshot age to accept from the account cache
+    #[serde(default)]
+    max_age_ms: Option<i64>,
+    #[serde(default)]
+    force_refresh: bool,
+}
+
+#[derive(Serialize)]
+struct GetAggregatePortfolioResponse {
+    portfolio: Option<AggregatePortfolio>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<AccountWarning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Serialize)]
+struct GetRateLimitsResponse {
+    limits: Vec<RateLimitStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetTracesRequest {
+    /// Number of most recent entries (default: whole buffer)
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct GetTracesResponse {
+    entries: Vec<TraceEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Serialize)]
+struct RenewAccessTokenResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<PluginError>,
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    /// Client created with OAuth credentials
+    initialized: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    circuit: Option<CircuitStatus>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    rate_limits: Vec<RateLimitStatus>,
+}
 
 // --- State Management ---
 
@@ -36,6 +381,10 @@ struct BrokerState {
     client: Option<ETradeClient>,
     orders: HashMap<String, Order>,
     next_order_id: u64,
+    quote_cache: QuoteCache,
+    refresh_position_prices: bool,
+    accounts: AccountDirectory,
+    account_cache: AccountCache,
 }
 
 impl BrokerState {
@@ -44,6 +393,10 @@ impl BrokerState {
             client: None,
             orders: HashMap::new(),
             next_order_id: 1,
+            quote_cache: QuoteCache::new(DEFAULT_QUOTE_TTL_MS),
+            refresh_position_prices: true,
+            accounts: AccountDirectory::default(),
+            account_cache: AccountCache::new(DEFAULT_ACCOUNT_CACHE_TTL_MS),
         }
     }
 }
@@ -54,19 +407,187 @@ lazy_static::lazy_static! {
 
 // --- WASM Exports ---
 
-/// Memory allocation for host communication
+/// Memory allocation for host communication: request buffers, and the
+/// `http_request` response buffers the host writes into
 #[no_mangle]
 pub extern "C" fn alloc(len: i32) -> i32 {
-    let mut buf: Vec<u8> = Vec::with_capacity(len as usize);
+    let mut buf: Vec<u8> = Vec::with_capacity(len.max(0) as usize);
     let ptr = buf.as_mut_ptr();
     std::mem::forget(buf);
     ptr as usize as i32
 }
 
+/// Release a buffer returned by `alloc` (including response buffers) once
+/// the host has copied it out. `len` must be the length passed to `alloc`.
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: i32, len: i32) {
+    if ptr == 0 || len <= 0 {
+        return;
+    }
+    unsafe {
+        drop(Vec::from_raw_parts(
+            ptr as usize as *mut u8,
+            0,
+            len as usize,
+        ));
+    }
+}
+
+/// Run an export's handler on the host's request buffer
+fn export(ptr: i32, len: i32, handler: fn(&[u8]) -> Vec<u8>) -> u64 {
+    let response = match request_bytes(ptr, len) {
+        Ok(request) => handler(request),
+        Err(e) => error_response(e),
+    };
+    into_host_buffer(response)
+}
+
+/// Borrow the request buffer. It stays owned by the host, which frees it
+/// with `dealloc` after the export returns.
+fn request_bytes<'a>(ptr: i32, len: i32) -> Result<&'a [u8], PluginError> {
+    if len < 0 || (ptr == 0 && len > 0) {
+        return Err(PluginError::invalid_request(format!(
+            "Invalid request buffer (ptr={}, len={})",
+            ptr, len
+        )));
+    }
+
+    // A zero-length buffer may come with a null pointer, which
+    // `from_raw_parts` does not allow
+    if len == 0 {
+        Ok(&[])
+    } else {
+        Ok(unsafe { slice::from_raw_parts(ptr as *const u8, len as usize) })
+    }
+}
+
+/// Copy a response into an `alloc` buffer and pack it as `ptr << 32 | len`
+fn into_host_buffer(bytes: Vec<u8>) -> u64 {
+    let out_len = bytes.len() as i32;
+    let out_ptr = alloc(out_len);
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr as *mut u8, out_len as usize);
+    }
+
+    ((out_ptr as u64) << 32) | (out_len as u64)
+}
+
 /// Initialize plugin with configuration
 #[no_mangle]
 pub extern "C" fn initialize(ptr: i32, len: i32) -> u64 {
-    let config_json: serde_json::Value = parse_request(ptr, len);
+    export(ptr, len, handle_initialize)
+}
+
+/// Get available accounts
+#[no_mangle]
+pub extern "C" fn get_accounts(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_accounts)
+}
+
+/// Get a single account with balance and positions
+#[no_mangle]
+pub extern "C" fn get_account(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_account)
+}
+
+/// Get positions for an account
+#[no_mangle]
+pub extern "C" fn get_positions(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_positions)
+}
+
+/// Get individual tax lots for one or all positions of an account
+#[no_mangle]
+pub extern "C" fn get_position_lots(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_position_lots)
+}
+
+/// Submit an order
+#[no_mangle]
+pub extern "C" fn submit_order(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_submit_order)
+}
+
+/// Cancel an open order
+#[no_mangle]
+pub extern "C" fn cancel_order(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_cancel_order)
+}
+
+/// Resolve a company name or partial ticker to tradable symbols
+#[no_mangle]
+pub extern "C" fn lookup_symbol(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_lookup_symbol)
+}
+
+/// Get quotes, served from the short-lived quote cache when fresh
+#[no_mangle]
+pub extern "C" fn get_quotes(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_quotes)
+}
+
+/// List account transactions (trades, dividends, interest, fees, transfers)
+#[no_mangle]
+pub extern "C" fn get_transactions(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_transactions)
+}
+
+/// Get details for a single transaction
+#[no_mangle]
+pub extern "C" fn get_transaction_detail(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_transaction_detail)
+}
+
+/// Summarize dividend and interest income per symbol and month
+#[no_mangle]
+pub extern "C" fn get_income_report(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_income_report)
+}
+
+/// Compute realized P&L from executed trades
+#[no_mangle]
+pub extern "C" fn get_realized_pnl(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_realized_pnl)
+}
+
+/// Merge positions across accounts by symbol
+#[no_mangle]
+pub extern "C" fn get_aggregate_portfolio(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_aggregate_portfolio)
+}
+
+/// Remaining client-side request budget per endpoint family
+#[no_mangle]
+pub extern "C" fn get_rate_limits(_ptr: i32, _len: i32) -> u64 {
+    into_host_buffer(handle_get_rate_limits(&[]))
+}
+
+/// Reactivate an idle OAuth access token
+#[no_mangle]
+pub extern "C" fn renew_access_token(_ptr: i32, _len: i32) -> u64 {
+    into_host_buffer(handle_renew_access_token(&[]))
+}
+
+/// Circuit breaker state and remaining request budget
+#[no_mangle]
+pub extern "C" fn get_health(_ptr: i32, _len: i32) -> u64 {
+    into_host_buffer(handle_get_health(&[]))
+}
+
+/// Most recent traced E*TRADE calls (secrets redacted)
+#[no_mangle]
+pub extern "C" fn get_traces(ptr: i32, len: i32) -> u64 {
+    export(ptr, len, handle_get_traces)
+}
+
+// --- Export Handlers ---
+
+fn handle_initialize(request: &[u8]) -> Vec<u8> {
+    let config_json: serde_json::Value = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
 
     let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
 
@@ -93,27 +614,57 @@ pub extern "C" fn initialize(ptr: i32, len: i32) -> u64 {
         .get("is_sandbox")
         .and_then(|v| v.as_bool())
         .unwrap_or(true);
+    let quote_cache_ttl_ms = config_json
+        .get("quote_cache_ttl_ms")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(DEFAULT_QUOTE_TTL_MS);
+    let refresh_position_prices = config_json
+        .get("refresh_position_prices")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let account_cache_ttl_ms = config_json
+        .get("account_cache_ttl_ms")
+        .and_then(|v| v.as_i64())
+        .unwrap_or(DEFAULT_ACCOUNT_CACHE_TTL_MS);
+    let include_closed_accounts = config_json
+        .get("include_closed_accounts")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    // `log_level` governs all stderr output, not just traced calls
+    let tracer = Tracer::from_config(&config_json);
+    trace::set_log_level(tracer.level());
 
     // Validate configuration
     if consumer_key.is_empty() || consumer_secret.is_empty() {
-        return serialize_response(&serde_json::json!({
-            "success": false,
-            "error": "Missing required configuration: consumer_key or consumer_secret"
-        }));
+        return error_response(PluginError::validation(
+            "Missing required configuration: consumer_key or consumer_secret",
+        ));
     }
 
-    // Check if OAuth tokens are available
-    let has_tokens = oauth_token.is_some() && oauth_token_secret.is_some();
+    state.quote_cache.set_ttl(quote_cache_ttl_ms);
+    state.quote_cache.clear();
+    state.refresh_position_prices = refresh_position_prices;
+    state.accounts.clear();
+    state.account_cache.set_ttl(account_cache_ttl_ms);
+    state.account_cache.clear();
 
-    if has_tokens {
+    // Check if OAuth tokens are available
+    if let (Some(oauth_token), Some(oauth_token_secret)) = (oauth_token, oauth_token_secret) {
         // Create E*TRADE client with full credentials
         let client = ETradeClient::new(
             consumer_key,
             consumer_secret,
-            oauth_token.unwrap(),
-            oauth_token_secret.unwrap(),
+            oauth_token,
+            oauth_token_secret,
             is_sandbox,
-        );
+        )
+        .include_closed_accounts(include_closed_accounts)
+        .retry_policy(RetryPolicy::from_config(&config_json))
+        .rate_limiter(RateLimiter::from_config(&config_json))
+        .circuit_breaker(CircuitBreaker::from_config(&config_json))
+        .timeouts(Timeouts::from_config(&config_json))
+        .tracer(tracer);
         state.client = Some(client);
 
         serialize_response(&serde_json::json!({
@@ -130,80 +681,259 @@ pub extern "C" fn initialize(ptr: i32, len: i32) -> u64 {
     }
 }
 
-/// Get available accounts
-#[no_mangle]
-pub extern "C" fn get_accounts(ptr: i32, len: i32) -> u64 {
-    let _req: GetAccountsRequest = parse_request(ptr, len);
+fn handle_get_accounts(request: &[u8]) -> Vec<u8> {
+    let req: AccountsRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
 
-    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
 
     let client = match state.client.as_ref() {
         Some(c) => c,
         None => {
-            return serialize_response(&GetAccountsResponse {
-                accounts: vec![create_error_account(
-                    "Plugin not initialized or OAuth not completed",
-                )],
-            });
+            return serialize_response(&AccountsResponse::error(PluginError::not_initialized()));
         }
     };
 
-    match client.list_accounts() {
-        Ok(accounts) => {
-            let response = GetAccountsResponse { accounts };
-            serialize_response(&response)
-        }
+    let infos = match state.accounts.refresh(client) {
+        Ok(infos) => infos,
         Err(e) => {
-            eprintln!("[broker-etrade] Failed to fetch accounts: {}", e);
-            serialize_response(&GetAccountsResponse {
-                accounts: vec![create_error_account(&e)],
-            })
+            trace::log_error("Failed to fetch accounts", &e);
+            return serialize_response(&AccountsResponse::error(e));
         }
+    };
+
+    let mut accounts = Vec::new();
+    let mut warnings = Vec::new();
+    for info in infos.iter().filter(|i| client.is_listed(i)) {
+        if req.accounts_only {
+            accounts.push(client.account_stub(info));
+            continue;
+        }
+
+        let (snapshot, errors) = load_account_cached(
+            client,
+            &mut state.account_cache,
+            info,
+            req.max_age_ms,
+            req.force_refresh,
+        );
+        accounts.push(snapshot.summary);
+        warnings.extend(errors);
+    }
+
+    for w in &warnings {
+        trace::log_error(
+            &format!("Account {} {} unavailable", w.account_id, w.source),
+            &w.error,
+        );
     }
+
+    serialize_response(&AccountsResponse {
+        accounts,
+        warnings,
+        error: None,
+    })
 }
 
-/// Get positions for an account
-#[no_mangle]
-pub extern "C" fn get_positions(ptr: i32, len: i32) -> u64 {
-    let req: GetPositionsRequest = parse_request(ptr, len);
+fn handle_get_account(request: &[u8]) -> Vec<u8> {
+    let req: GetAccountRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
 
-    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetAccountResponse {
+                account: None,
+                warnings: vec![],
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    let info = match state.accounts.info(&account_key) {
+        Some(info) => info.clone(),
+        None => {
+            return serialize_response(&GetAccountResponse {
+                account: None,
+                warnings: vec![],
+                error: Some(PluginError::validation(format!(
+                    "Unknown account: {}",
+                    req.account_id
+                ))),
+            });
+        }
+    };
+
+    let (snapshot, warnings) = load_account_cached(
+        client,
+        &mut state.account_cache,
+        &info,
+        req.max_age_ms,
+        req.force_refresh,
+    );
+
+    serialize_response(&GetAccountResponse {
+        account: Some(snapshot.summary),
+        warnings,
+        error: None,
+    })
+}
+
+fn handle_get_positions(request: &[u8]) -> Vec<u8> {
+    let req: PositionsRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
 
     let client = match state.client.as_ref() {
         Some(c) => c,
         None => {
-            return serialize_response(&GetPositionsResponse { positions: vec![] });
+            return serialize_response(&PositionsResponse::error(PluginError::not_initialized()));
         }
     };
+    let account_key = state.accounts.resolve(client, &req.base.account_id);
+
+    match client.get_position_details(&account_key, &req.query) {
+        Ok(mut details) => {
+            if state.refresh_position_prices {
+                refresh_position_prices(client, &mut state.quote_cache, &mut details);
+            }
+
+            let mut positions = Vec::with_capacity(details.len());
+            let mut extensions = HashMap::new();
+
+            for mut detail in details {
+                if req.include_lots {
+                    embed_lots(client, &account_key, &mut detail);
+                }
+                if !detail.extensions.is_empty() {
+                    // A symbol can be held in more than one position (e.g.
+                    // long and short), so key by E*TRADE's position ID
+                    let key = detail
+                        .position_id
+                        .clone()
+                        .unwrap_or_else(|| detail.position.symbol_id.clone());
+                    detail.extensions.insert(
+                        "symbol_id".to_string(),
+                        serde_json::json!(detail.position.symbol_id),
+                    );
+                    extensions.insert(key, detail.extensions);
+                }
+                positions.push(detail.position);
+            }
 
-    match client.get_positions(&req.account_id) {
-        Ok(positions) => {
-            let response = GetPositionsResponse { positions };
-            serialize_response(&response)
+            serialize_response(&PositionsResponse {
+                positions,
+                extensions,
+                error: None,
+            })
         }
         Err(e) => {
-            eprintln!("[broker-etrade] Failed to fetch positions: {}", e);
-            serialize_response(&GetPositionsResponse { positions: vec![] })
+            trace::log_error("Failed to fetch positions", &e);
+            serialize_response(&PositionsResponse::error(e))
         }
     }
 }
 
-/// Submit an order
-#[no_mangle]
-pub extern "C" fn submit_order(ptr: i32, len: i32) -> u64 {
-    let req: SubmitOrderRequest = parse_request(ptr, len);
-    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+fn handle_get_position_lots(request: &[u8]) -> Vec<u8> {
+    let req: GetPositionLotsRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
 
     let client = match state.client.as_ref() {
         Some(c) => c,
         None => {
-            return serialize_response(&SubmitOrderResponse {
-                order: create_error_order(&req, "Plugin not initialized"),
+            return serialize_response(&GetPositionLotsResponse {
+                positions: vec![],
+                error: Some(PluginError::not_initialized()),
             });
         }
     };
+    let account_key = state.accounts.resolve(client, &req.account_id);
 
-    match client.submit_order(&req.account_id, &req.order) {
+    let query = PortfolioQuery::default();
+    let details = match client.get_position_details(&account_key, &query) {
+        Ok(d) => d,
+        Err(e) => {
+            trace::log_error("Failed to fetch positions", &e);
+            return serialize_response(&GetPositionLotsResponse {
+                positions: vec![],
+                error: Some(e),
+            });
+        }
+    };
+
+    let mut positions = Vec::new();
+    for detail in details {
+        let symbol_matches = req
+            .symbol
+            .as_ref()
+            .map(|s| detail.position.symbol_id.eq_ignore_ascii_case(s))
+            .unwrap_or(true);
+        let id_matches = req
+            .position_id
+            .as_ref()
+            .map(|id| detail.position_id.as_ref() == Some(id))
+            .unwrap_or(true);
+        if !symbol_matches || !id_matches {
+            continue;
+        }
+
+        match client.get_position_lots(&account_key, &detail) {
+            Ok(lots) => positions.push(PositionLots {
+                symbol: detail.position.symbol_id,
+                position_id: detail.position_id,
+                lots,
+            }),
+            Err(e) => {
+                trace::log_error("Failed to fetch position lots", &e);
+                return serialize_response(&GetPositionLotsResponse {
+                    positions,
+                    error: Some(e),
+                });
+            }
+        }
+    }
+
+    serialize_response(&GetPositionLotsResponse {
+        positions,
+        error: None,
+    })
+}
+
+fn handle_submit_order(request: &[u8]) -> Vec<u8> {
+    let req: SubmitOrderRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&SubmitOrderResponse::error(PluginError::not_initialized()));
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    match client.submit_order(&account_key, &req.order) {
         Ok(mut order) => {
             // Track order locally
             let order_id = order.id.clone();
@@ -212,74 +942,850 @@ pub extern "C" fn submit_order(ptr: i32, len: i32) -> u64 {
             }
             state.orders.insert(order_id, order.clone());
             state.next_order_id += 1;
+            // Cash and positions change once the order is placed
+            state.account_cache.evict(&account_key);
 
-            serialize_response(&SubmitOrderResponse { order })
-        }
-        Err(e) => {
-            eprintln!("[broker-etrade] Order failed: {}", e);
             serialize_response(&SubmitOrderResponse {
-                order: create_error_order(&req, &e),
+                order: Some(order),
+                error: None,
             })
         }
+        Err(e) => {
+            trace::log_error("Order failed", &e);
+            serialize_response(&SubmitOrderResponse::error(e))
+        }
     }
 }
 
-// --- Helper Functions ---
-
-fn parse_request<T: serde::de::DeserializeOwned>(ptr: i32, len: i32) -> T {
-    let slice = unsafe { slice::from_raw_parts(ptr as *const u8, len as usize) };
-    serde_json::from_slice(slice).expect("Failed to parse request")
-}
-
-fn serialize_response<T: serde::Serialize>(response: &T) -> u64 {
-    let res_bytes = serde_json::to_vec(response).expect("Failed to serialize response");
-
-    let out_len = res_bytes.len() as i32;
-    let out_ptr = alloc(out_len);
+fn handle_cancel_order(request: &[u8]) -> Vec<u8> {
+    let req: CancelOrderRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
 
-    unsafe {
-        std::ptr::copy_nonoverlapping(res_bytes.as_ptr(), out_ptr as *mut u8, out_len as usize);
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&CancelOrderResponse {
+                order: None,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    match client.cancel_order(&account_key, &req.order_id) {
+        Ok(order) => {
+            // Buying power is released once the cancel goes through
+            state.account_cache.evict(&account_key);
+            serialize_response(&CancelOrderResponse {
+                order: Some(order),
+                error: None,
+            })
+        }
+        Err(e) => {
+            trace::log_error("Cancel failed", &e);
+            serialize_response(&CancelOrderResponse {
+                order: None,
+                error: Some(e),
+            })
+        }
     }
+}
 
-    ((out_ptr as u64) << 32) | (out_len as u64)
+fn handle_lookup_symbol(request: &[u8]) -> Vec<u8> {
+    let req: LookupSymbolRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&LookupSymbolResponse {
+                results: vec![],
+                exact_match: false,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+
+    match client.lookup_symbol(&req.search) {
+        Ok(results) => {
+            let search = req.search.trim();
+            let exact_match = results
+                .iter()
+                .any(|r| r.symbol.eq_ignore_ascii_case(search));
+
+            serialize_response(&LookupSymbolResponse {
+                results,
+                exact_match,
+                error: None,
+            })
+        }
+        Err(e) => {
+            trace::log_error("Symbol lookup failed", &e);
+            serialize_response(&LookupSymbolResponse {
+                results: vec![],
+                exact_match: false,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+fn handle_get_quotes(request: &[u8]) -> Vec<u8> {
+    let req: GetQuotesRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetQuotesResponse {
+                quotes: vec![],
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+
+    match state
+        .quote_cache
+        .get_quotes(client, &req.symbols, req.detail, req.force_refresh)
+    {
+        Ok(quotes) => serialize_response(&GetQuotesResponse {
+            quotes,
+            error: None,
+        }),
+        Err(e) => {
+            trace::log_error("Failed to fetch quotes", &e);
+            serialize_response(&GetQuotesResponse {
+                quotes: vec![],
+                error: Some(e),
+            })
+        }
+    }
 }
 
-fn create_error_account(error: &str) -> AccountSummary {
-    AccountSummary {
-        id: "error".to_string(),
-        name: format!("Error: {}", error),
-        broker_id: "broker-etrade".to_string(),
-        is_paper: true,
-        balance: AccountBalance {
-            currency: "USD".to_string(),
-            total_equity: 0.0,
-            available_cash: 0.0,
-            buying_power: 0.0,
-            locked_cash: 0.0,
-        },
-        positions: vec![],
-        updated_at: Utc::now(),
-        extensions: None,
-    }
-}
-
-fn create_error_order(req: &SubmitOrderRequest, error: &str) -> Order {
-    Order {
-        id: format!("error_{}", Utc::now().timestamp_millis()),
-        request: req.order.clone(),
-        status: OrderStatus::Rejected,
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-        average_filled_price: None,
-        filled_quantity: 0.0,
-        extensions: Some({
-            let mut map = HashMap::new();
-            map.insert(
-                "error".to_string(),
-                serde_json::Value::String(error.to_string()),
+fn handle_get_transactions(request: &[u8]) -> Vec<u8> {
+    let req: GetTransactionsRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetTransactionsResponse {
+                error: Some(PluginError::not_initialized()),
+                ..Default::default()
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    let result = if req.fetch_all {
+        client
+            .list_all_transactions(&account_key, &req.query)
+            .map(|transactions| GetTransactionsResponse {
+                total_count: Some(transactions.len() as i64),
+                transactions,
+                ..Default::default()
+            })
+    } else {
+        client
+            .list_transactions(&account_key, &req.query)
+            .map(|page| GetTransactionsResponse {
+                transactions: page.transactions,
+                next_marker: page.next_marker,
+                more: page.more,
+                total_count: page.total_count,
+                error: None,
+            })
+    };
+
+    match result {
+        Ok(response) => serialize_response(&response),
+        Err(e) => {
+            trace::log_error("Failed to fetch transactions", &e);
+            serialize_response(&GetTransactionsResponse {
+                error: Some(e),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+fn handle_get_transaction_detail(request: &[u8]) -> Vec<u8> {
+    let req: GetTransactionDetailRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetTransactionDetailResponse {
+                transaction: None,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    match client.get_transaction(&account_key, &req.transaction_id) {
+        Ok(transaction) => serialize_response(&GetTransactionDetailResponse {
+            transaction: Some(transaction),
+            error: None,
+        }),
+        Err(e) => {
+            trace::log_error("Failed to fetch transaction", &e);
+            serialize_response(&GetTransactionDetailResponse {
+                transaction: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+fn handle_get_income_report(request: &[u8]) -> Vec<u8> {
+    let req: GetIncomeReportRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetIncomeReportResponse {
+                report: None,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    let query = TransactionQuery {
+        start_date: req.start_date,
+        end_date: req.end_date,
+        sort_order: SortOrder::Asc,
+        ..Default::default()
+    };
+
+    match client.list_all_transactions(&account_key, &query) {
+        Ok(transactions) => {
+            // Cost basis is optional; the report is still useful without it
+            let positions = client.get_positions(&account_key).unwrap_or_else(|e| {
+                trace::log_error("Income report without cost basis", &e);
+                vec![]
+            });
+
+            let report = income::build_income_report(
+                &transactions,
+                &positions,
+                req.start_date,
+                req.end_date,
             );
-            map
+            serialize_response(&GetIncomeReportResponse {
+                report: Some(report),
+                error: None,
+            })
+        }
+        Err(e) => {
+            trace::log_error("Failed to build income report", &e);
+            serialize_response(&GetIncomeReportResponse {
+                report: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+fn handle_get_realized_pnl(request: &[u8]) -> Vec<u8> {
+    let req: GetRealizedPnlRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetRealizedPnlResponse {
+                report: None,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+    let account_key = state.accounts.resolve(client, &req.account_id);
+
+    let query =
+        TransactionQuery {
+            start_date: Some(req.history_start_date.unwrap_or_else(|| {
+                Utc::now().date_naive() - chrono::Duration::days(MAX_HISTORY_DAYS)
+            })),
+            end_date: req.end_date,
+            sort_order: SortOrder::Asc,
+            ..Default::default()
+        };
+
+    match client.list_all_transactions(&account_key, &query) {
+        Ok(transactions) => {
+            let report = realized_pnl::compute_realized_pnl(
+                &transactions,
+                req.method,
+                req.period,
+                &req.specific_lots,
+                req.start_date,
+                req.end_date,
+            );
+            serialize_response(&GetRealizedPnlResponse {
+                report: Some(report),
+                error: None,
+            })
+        }
+        Err(e) => {
+            trace::log_error("Failed to compute realized P&L", &e);
+            serialize_response(&GetRealizedPnlResponse {
+                report: None,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+fn handle_get_aggregate_portfolio(request: &[u8]) -> Vec<u8> {
+    let req: GetAggregatePortfolioRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+
+    let mut guard = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let state = &mut *guard;
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&GetAggregatePortfolioResponse {
+                portfolio: None,
+                warnings: vec![],
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+
+    let infos = match state.accounts.refresh(client) {
+        Ok(infos) => infos,
+        Err(e) => {
+            trace::log_error("Failed to fetch accounts", &e);
+            return serialize_response(&GetAggregatePortfolioResponse {
+                portfolio: None,
+                warnings: vec![],
+                error: Some(e),
+            });
+        }
+    };
+
+    let wanted: Vec<String> = req
+        .account_ids
+        .iter()
+        .map(|id| state.accounts.resolve(client, id))
+        .collect();
+
+    let mut accounts = Vec::new();
+    let mut warnings = Vec::new();
+    for info in infos
+        .iter()
+        .filter(|i| client.is_listed(i) && !i.is_closed())
+        .filter(|i| wanted.is_empty() || wanted.contains(&i.account_id_key))
+    {
+        let (snapshot, errors) = load_account_cached(
+            client,
+            &mut state.account_cache,
+            info,
+            req.max_age_ms,
+            req.force_refresh,
+        );
+        // A failed balance does not affect the merge; failed positions
+        // leave the account out
+        let (position_errors, _): (Vec<_>, Vec<_>) =
+            errors.into_iter().partition(|e| e.source == "positions");
+        if position_errors.is_empty() {
+            accounts.push((info.account_id.clone(), snapshot.positions));
+        }
+        warnings.extend(position_errors);
+    }
+
+    serialize_response(&GetAggregatePortfolioResponse {
+        portfolio: Some(aggregate::aggregate_positions(&accounts)),
+        warnings,
+        error: None,
+    })
+}
+
+fn handle_get_rate_limits(_request: &[u8]) -> Vec<u8> {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+    match state.client.as_ref() {
+        Some(client) => serialize_response(&GetRateLimitsResponse {
+            limits: client.rate_limit_status(),
+            error: None,
+        }),
+        None => serialize_response(&GetRateLimitsResponse {
+            limits: vec![],
+            error: Some(PluginError::not_initialized()),
         }),
-        persona_id: req.order.persona_id.clone(),
+    }
+}
+
+fn handle_renew_access_token(_request: &[u8]) -> Vec<u8> {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+    let client = match state.client.as_ref() {
+        Some(c) => c,
+        None => {
+            return serialize_response(&RenewAccessTokenResponse {
+                success: false,
+                error: Some(PluginError::not_initialized()),
+            });
+        }
+    };
+
+    match client.renew_access_token() {
+        Ok(()) => serialize_response(&RenewAccessTokenResponse {
+            success: true,
+            error: None,
+        }),
+        Err(e) => {
+            trace::log_error("Failed to renew access token", &e);
+            serialize_response(&RenewAccessTokenResponse {
+                success: false,
+                error: Some(e),
+            })
+        }
+    }
+}
+
+fn handle_get_health(_request: &[u8]) -> Vec<u8> {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let client = state.client.as_ref();
+
+    serialize_response(&HealthResponse {
+        initialized: client.is_some(),
+        circuit: client.map(|c| c.circuit_status()),
+        rate_limits: client.map(|c| c.rate_limit_status()).unwrap_or_default(),
+    })
+}
+
+fn handle_get_traces(request: &[u8]) -> Vec<u8> {
+    let req: GetTracesRequest = match parse_request(request) {
+        Ok(r) => r,
+        Err(e) => return error_response(e),
+    };
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+
+    match state.client.as_ref() {
+        Some(client) => serialize_response(&GetTracesResponse {
+            entries: client.traces(req.limit.unwrap_or(usize::MAX)),
+            error: None,
+        }),
+        None => serialize_response(&GetTracesResponse {
+            entries: vec![],
+            error: Some(PluginError::not_initialized()),
+        }),
+    }
+}
+
+// --- Helper Functions ---
+
+/// Serve an account from the snapshot cache, loading it when stale.
+/// Only fully loaded accounts are cached.
+fn load_account_cached(
+    client: &ETradeClient,
+    cache: &mut AccountCache,
+    info: &AccountInfo,
+    max_age_ms: Option<i64>,
+    force_refresh: bool,
+) -> (AccountSnapshot, Vec<AccountWarning>) {
+    if !force_refresh {
+        if let Some(snapshot) = cache.get(&info.account_id_key, max_age_ms) {
+            return (snapshot, vec![]);
+        }
+    }
+
+    let (snapshot, errors) = client.load_account(info);
+    if errors.is_empty() {
+        cache.insert(&info.account_id_key, &snapshot);
+    }
+    (snapshot, errors)
+}
+
+/// Fetch lots for a position into its extensions; a failure is recorded
+/// as `lots_error` rather than failing the whole positions call
+fn embed_lots(client: &ETradeClient, account_id: &str, detail: &mut PositionDetail) {
+    match client.get_position_lots(account_id, detail) {
+        Ok(lots) => {
+            detail.extensions.insert(
+                "lots".to_string(),
+                serde_json::to_value(lots).unwrap_or_default(),
+            );
+        }
+        Err(e) => {
+            detail.extensions.insert(
+                "lots_error".to_string(),
+                serde_json::to_value(&e).unwrap_or_default(),
+            );
+        }
+    }
+}
+
+/// Update `current_price` and unrealized P&L of equity positions from
+/// cached quotes. Options keep portfolio prices since their OSI symbols are
+/// not valid quote keys. Keeps portfolio prices if the quote request fails.
+fn refresh_position_prices(
+    client: &ETradeClient,
+    cache: &mut QuoteCache,
+    details: &mut [PositionDetail],
+) {
+    let symbols: Vec<String> = details
+        .iter()
+        .filter(|d| d.asset_class == AssetClass::Equity)
+        .map(|d| d.position.symbol_id.clone())
+        .collect();
+    if symbols.is_empty() {
+        return;
+    }
+
+    let quotes = match cache.get_quotes(client, &symbols, QuoteDetail::Intraday, false) {
+        Ok(q) => q,
+        Err(e) => {
+            trace::log_error("Failed to refresh position prices", &e);
+            return;
+        }
+    };
+
+    for detail in details.iter_mut() {
+        if detail.asset_class != AssetClass::Equity {
+            continue;
+        }
+        let pos = &mut detail.position;
+        let last = quotes
+            .iter()
+            .find(|q| q.symbol.eq_ignore_ascii_case(&pos.symbol_id))
+            .and_then(|q| q.last_price);
+
+        if let Some(price) = last {
+            pos.current_price = price;
+            // Negative quantity for shorts flips the sign of the gain
+            pos.unrealized_pnl = (price - pos.average_price) * pos.quantity;
+            pos.unrealized_pnl_percent = if pos.average_price != 0.0 {
+                (price - pos.average_price) / pos.average_price * 100.0 * pos.quantity.signum()
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+/// Deserialize an export's request
+fn parse_request<T: serde::de::DeserializeOwned>(request: &[u8]) -> Result<T, PluginError> {
+    serde_json::from_slice(request).map_err(|e| PluginError::invalid_request(e.to_string()))
+}
+
+fn error_response(error: PluginError) -> Vec<u8> {
+    trace::log(LogLevel::for_error(&error), &error.to_string());
+    serialize_response(&ErrorEnvelope::new(error))
+}
+
+/// Fallback body when even the error envelope cannot be serialized
+const SERIALIZATION_FAILED: &[u8] =
+    br#"{"success":false,"error":{"kind":"serialization","message":"Failed to serialize response"}}"#;
+
+fn serialize_response<T: serde::Serialize>(response: &T) -> Vec<u8> {
+    serde_json::to_vec(response).unwrap_or_else(|e| {
+        trace::log(
+            LogLevel::Error,
+            &format!("Failed to serialize response: {}", e),
+        );
+        serde_json::to_vec(&ErrorEnvelope::new(PluginError::serialization(
+            e.to_string(),
+        )))
+        .unwrap_or_else(|_| SERIALIZATION_FAILED.to_vec())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use test_support::call;
+
+    type Handler = fn(&[u8]) -> Vec<u8>;
+
+    /// Handlers that deserialize a request, with a valid request for each
+    const HANDLERS: [(&str, Handler, &str); 15] = [
+        (
+            "initialize",
+            handle_initialize,
+            r#"{"consumer_key":"k","consumer_secret":"s"}"#,
+        ),
+        (
+            "get_accounts",
+            handle_get_accounts,
+            r#"{"accounts_only":true}"#,
+        ),
+        (
+            "get_account",
+            handle_get_account,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "get_positions",
+            handle_get_positions,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "get_position_lots",
+            handle_get_position_lots,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "submit_order",
+            handle_submit_order,
+            r#"{"account_id":"12345678","order":{"symbol_id":"AAPL","side":"Buy","order_type":"Market","quantity":1.0,"limit_price":null,"persona_id":""}}"#,
+        ),
+        (
+            "cancel_order",
+            handle_cancel_order,
+            r#"{"account_id":"12345678","order_id":"1"}"#,
+        ),
+        (
+            "lookup_symbol",
+            handle_lookup_symbol,
+            r#"{"search":"apple"}"#,
+        ),
+        ("get_quotes", handle_get_quotes, r#"{"symbols":["AAPL"]}"#),
+        (
+            "get_transactions",
+            handle_get_transactions,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "get_transaction_detail",
+            handle_get_transaction_detail,
+            r#"{"account_id":"12345678","transaction_id":"1"}"#,
+        ),
+        (
+            "get_income_report",
+            handle_get_income_report,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "get_realized_pnl",
+            handle_get_realized_pnl,
+            r#"{"account_id":"12345678"}"#,
+        ),
+        (
+            "get_aggregate_portfolio",
+            handle_get_aggregate_portfolio,
+            r#"{}"#,
+        ),
+        ("get_traces", handle_get_traces, r#"{"limit":10}"#),
+    ];
+
+    /// Handlers that take no request and must ignore whatever is passed
+    const NO_ARG_HANDLERS: [(&str, Handler); 3] = [
+        ("get_rate_limits", handle_get_rate_limits),
+        ("renew_access_token", handle_renew_access_token),
+        ("get_health", handle_get_health),
+    ];
+
+    fn assert_invalid_request(name: &str, response: &serde_json::Value) {
+        assert_eq!(response["success"], false, "{}: {}", name, response);
+        assert_eq!(
+            response["error"]["kind"], "invalid_request",
+            "{}: {}",
+            name, response
+        );
+    }
+
+    fn random_buffers() -> Vec<Vec<u8>> {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(41);
+        (0..32)
+            .map(|_| {
+                let mut buf = vec![0u8; rng.gen_range(1..256)];
+                rng.fill(&mut buf[..]);
+                buf
+            })
+            .collect()
+    }
+
+    #[test]
+    fn handlers_reject_empty_requests() {
+        let _state = test_support::lock_state();
+        for (name, handler, _) in HANDLERS {
+            assert_invalid_request(name, &call(handler, b""));
+        }
+    }
+
+    #[test]
+    fn handlers_reject_truncated_requests() {
+        let _state = test_support::lock_state();
+        for (name, handler, request) in HANDLERS {
+            for cut in [1, request.len() / 2, request.len() - 1] {
+                let response = call(handler, &request.as_bytes()[..cut]);
+                assert_invalid_request(name, &response);
+            }
+        }
+    }
+
+    #[test]
+    fn handlers_reject_random_requests() {
+        let _state = test_support::lock_state();
+        let buffers = random_buffers();
+        for (name, handler, _) in HANDLERS {
+            for buf in &buffers {
+                assert_invalid_request(name, &call(handler, buf));
+            }
+        }
+    }
+
+    #[test]
+    fn no_arg_handlers_ignore_bad_requests() {
+        let _state = test_support::lock_state();
+        for (name, handler) in NO_ARG_HANDLERS {
+            assert!(call(handler, b"").is_object(), "{}", name);
+            for buf in random_buffers() {
+                assert!(call(handler, &buf).is_object(), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn initialize_rejects_missing_credentials() {
+        let _state = test_support::lock_state();
+        let response = call(handle_initialize, br#"{"consumer_key":"k"}"#);
+        assert_eq!(response["success"], false);
+        assert_eq!(response["error"]["kind"], "validation");
+    }
+
+    #[test]
+    fn get_accounts_reports_errors_without_placeholder_accounts() {
+        let _state = test_support::lock_state();
+        *STATE.lock().unwrap_or_else(|e| e.into_inner()) = BrokerState::new();
+
+        let response = call(handle_get_accounts, b"{}");
+        assert_eq!(response["accounts"], serde_json::json!([]));
+        assert_eq!(response["error"]["kind"], "not_initialized");
+    }
+
+    #[test]
+    fn submit_order_reports_errors_without_placeholder_orders() {
+        let _state = test_support::lock_state();
+        *STATE.lock().unwrap_or_else(|e| e.into_inner()) = BrokerState::new();
+
+        let (_, _, request) = HANDLERS[5];
+        let response = call(handle_submit_order, request.as_bytes());
+        assert_eq!(response["order"], serde_json::Value::Null);
+        assert_eq!(response["error"]["kind"], "not_initialized");
+    }
+
+    #[test]
+    fn traces_are_kept_when_logging_is_off() {
+        let _state = test_support::lock_state();
+        let config = br#"{
+            "consumer_key": "k",
+            "consumer_secret": "s",
+            "oauth_token": "t",
+            "oauth_token_secret": "ts",
+            "log_level": "off"
+        }"#;
+        assert_eq!(call(handle_initialize, config)["success"], true);
+        call(handle_get_accounts, br#"{"accounts_only":true}"#);
+        let traces = call(handle_get_traces, b"{}");
+
+        *STATE.lock().unwrap_or_else(|e| e.into_inner()) = BrokerState::new();
+        trace::set_log_level(LogLevel::Warn);
+        test_support::take_sent();
+
+        assert_eq!(traces["entries"][0]["path"], "/v1/accounts/list");
+    }
+
+    const SOAK_CONFIG: &[u8] = br#"{
+        "consumer_key": "k",
+        "consumer_secret": "s",
+        "oauth_token": "t",
+        "oauth_token_secret": "ts",
+        "log_level": "error",
+        "trace_buffer_size": 50,
+        "rate_limit_accounts_per_second": 1000000,
+        "rate_limit_accounts_per_hour": 1000000000,
+        "rate_limit_market_per_second": 1000000,
+        "rate_limit_market_per_hour": 1000000000
+    }"#;
+
+    /// Memory stays flat once caches are warm and the trace buffer is
+    /// evicting its oldest entries
+    #[test]
+    fn memory_stays_flat_over_repeated_calls() {
+        let _state = test_support::lock_state();
+        assert_eq!(call(handle_initialize, SOAK_CONFIG)["success"], true);
+
+        let round = || {
+            let accounts = call(handle_get_accounts, b"{}");
+            assert_eq!(accounts["accounts"][0]["id"], "12345678");
+            call(
+                handle_get_account,
+                br#"{"account_id":"12345678","force_refresh":true}"#,
+            );
+            call(handle_get_positions, br#"{"account_id":"12345678"}"#);
+            call(
+                handle_get_quotes,
+                br#"{"symbols":["AAPL"],"force_refresh":true}"#,
+            );
+            call(handle_get_health, b"");
+            call(handle_get_traces, br#"{"limit":10}"#);
+            call(handle_get_accounts, br#"{"accounts_only":"#);
+            test_support::take_sent();
+        };
+
+        for _ in 0..100 {
+            round();
+        }
+        let baseline = test_support::live_bytes();
+        for _ in 0..2_000 {
+            round();
+        }
+        let growth = test_support::live_bytes() - baseline;
+        let traces = call(handle_get_traces, b"{}");
+
+        *STATE.lock().unwrap_or_else(|e| e.into_inner()) = BrokerState::new();
+        trace::set_log_level(LogLevel::Warn);
+
+        assert!(
+            growth < 16 * 1024,
+            "live bytes grew by {} over 2000 rounds",
+            growth
+        );
+        assert_eq!(traces["entries"].as_array().map(Vec::len), Some(50));
     }
 }
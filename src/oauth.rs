@@ -0,0 +1,238 @@
+//! OAuth 1.0a request signing
+//!
+//! `OAuthSigner` plugs into `HttpClient` and adds an HMAC-SHA1
+//! `Authorization` header to every outgoing request. Query parameters are
+//! signed as part of the parameter set, with the query string stripped from
+//! the base string URL, as required by RFC 5849.
+
+use crate::http::{HttpRequest, RequestSigner};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::collections::HashMap;
+
+type HmacSha1 = Hmac<Sha1>;
+
+pub struct OAuthSigner {
+    consumer_key: String,
+    consumer_secret: String,
+    token: String,
+    token_secret: String,
+}
+
+impl OAuthSigner {
+    pub fn new(
+        consumer_key: String,
+        consumer_secret: String,
+        token: String,
+        token_secret: String,
+    ) -> Self {
+        Self {
+            consumer_key,
+            consumer_secret,
+            token,
+            token_secret,
+        }
+    }
+
+    /// Generate OAuth 1.0a signature
+    fn generate_signature(
+        &self,
+        method: &str,
+        base_url: &str,
+        params: &[(String, String)],
+    ) -> String {
+        let mut encoded: Vec<(String, String)> = params
+            .iter()
+            .map(|(k, v)| (percent_encode(k), percent_encode(v)))
+            .collect();
+        encoded.sort();
+
+        let param_string: String = encoded
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let base_string = format!(
+            "{}&{}&{}",
+            method.to_uppercase(),
+            percent_encode(base_url),
+            percent_encode(&param_string)
+        );
+
+        let signing_key = format!(
+            "{}&{}",
+            percent_encode(&self.consumer_secret),
+            percent_encode(&self.token_secret)
+        );
+
+        let mut mac = HmacSha1::new_from_slice(signing_key.as_bytes())
+            .expect("HMAC can take key of any size");
+        mac.update(base_string.as_bytes());
+        let result = mac.finalize();
+
+        BASE64.encode(result.into_bytes())
+    }
+
+    /// Build OAuth Authorization header
+    fn build_auth_header(&self, method: &str, url: &str) -> String {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+
+        let nonce = format!("{:016x}", rand::random::<u64>());
+
+        self.authorization(method, url, timestamp, nonce)
+    }
+
+    /// Authorization header for a fixed timestamp and nonce
+    pub(crate) fn authorization(
+        &self,
+        method: &str,
+        url: &str,
+        timestamp: String,
+        nonce: String,
+    ) -> String {
+        let mut oauth_params = vec![
+            ("oauth_consumer_key".to_string(), self.consumer_key.clone()),
+            ("oauth_token".to_string(), self.token.clone()),
+            (
+                "oauth_signature_method".to_string(),
+                "HMAC-SHA1".to_string(),
+            ),
+            ("oauth_timestamp".to_string(), timestamp),
+            ("oauth_nonce".to_string(), nonce),
+            ("oauth_version".to_string(), "1.0".to_string()),
+        ];
+
+        let (base_url, query) = match url.split_once('?') {
+            Some((base, query)) => (base, query),
+            None => (url, ""),
+        };
+        let mut params = oauth_params.clone();
+        params.extend(query_params(query));
+
+        let signature = self.generate_signature(method, base_url, &params);
+        oauth_params.push(("oauth_signature".to_string(), signature));
+
+        let header_value: String = oauth_params
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, percent_encode(v)))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!("OAuth {}", header_value)
+    }
+}
+
+impl RequestSigner for OAuthSigner {
+    fn sign(&self, request: &mut HttpRequest) {
+        let header = self.build_auth_header(request.method.as_str(), &request.url);
+        request.headers.insert("Authorization".to_string(), header);
+    }
+}
+
+/// Decoded fields of a form-encoded OAuth response body
+/// (e.g. `oauth_problem=token_expired`)
+pub(crate) fn parse_form(body: &str) -> HashMap<String, String> {
+    query_params(body.trim()).into_iter().collect()
+}
+
+/// Decoded `key=value` pairs of a query string
+fn query_params(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let hex = |b: u8| (b as char).to_digit(16);
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// URL percent encoding (RFC 3986)
+pub(crate) fn percent_encode(s: &str) -> String {
+    let mut result = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char);
+            }
+            _ => {
+                result.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example request from the OAuth Core 1.0 specification (Appendix A)
+    fn spec_signer() -> OAuthSigner {
+        OAuthSigner::new(
+            "dpf43f3p2l4k3l03".to_string(),
+            "kd94hf93k423kf44".to_string(),
+            "nnch734d00sl2jdk".to_string(),
+            "pfkkdhi9sl3r4s00".to_string(),
+        )
+    }
+
+    #[test]
+    fn signs_query_parameters() {
+        let header = spec_signer().authorization(
+            "GET",
+            "http://photos.example.net/photos?file=vacation.jpg&size=original",
+            "1191242096".to_string(),
+            "kllo9940pd9333jh".to_string(),
+        );
+        assert!(
+            header.contains(r#"oauth_signature="tR3%2BTy81lMeYAr%2FFid0kMTYa%2FWM%3D""#),
+            "{}",
+            header
+        );
+    }
+
+    #[test]
+    fn query_string_changes_the_signature() {
+        let signer = spec_signer();
+        let sign = |url: &str| {
+            signer.authorization("GET", url, "1191242096".to_string(), "nonce".to_string())
+        };
+        assert_ne!(
+            sign("https://api.etrade.com/v1/accounts/list"),
+            sign("https://api.etrade.com/v1/accounts/list?count=50")
+        );
+    }
+
+    #[test]
+    fn parses_form_bodies() {
+        let form = parse_form("oauth_problem=token_expired&oauth_problem_advice=Renew%20token\n");
+        assert_eq!(form["oauth_problem"], "token_expired");
+        assert_eq!(form["oauth_problem_advice"], "Renew token");
+    }
+}
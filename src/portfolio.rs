@@ -0,0 +1,718 @@
+//! Portfolio model
+//!
+//! Wire types for `/v1/accounts/{id}/portfolio` and the position lots
+//! endpoint, plus `PositionDetail`, which carries the fields that do not fit
+//! in `models::portfolio::Position`.
+
+use crate::common::{millis_to_utc, value_to_string, LONG_TERM_DAYS};
+use crate::transactions::SortOrder;
+use chrono::{DateTime, Utc};
+use models::portfolio::Position;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Positions requested per portfolio page
+pub const PORTFOLIO_PAGE_SIZE: u32 = 100;
+
+/// Upper bound on pages followed, in case E*TRADE keeps returning a next page
+pub const MAX_PORTFOLIO_PAGES: u32 = 50;
+
+/// View blocks E*TRADE may attach to a position, depending on `view`
+const VIEW_BLOCKS: [&str; 5] = [
+    "Quick",
+    "Performance",
+    "Fundamental",
+    "OptionsWatch",
+    "Complete",
+];
+
+/// Extension key and the E*TRADE field names it is read from, in order
+const VIEW_FIELDS: [(&str, &[&str]); 12] = [
+    ("days_gain", &["daysGain"]),
+    ("days_gain_pct", &["daysGainPct"]),
+    ("change", &["change"]),
+    ("change_pct", &["changePct"]),
+    ("volume", &["volume"]),
+    ("pe_ratio", &["peRatio", "pe"]),
+    ("eps", &["eps"]),
+    ("dividend", &["dividend", "annualDividend"]),
+    ("dividend_yield", &["divYield"]),
+    ("market_cap", &["marketCap"]),
+    ("week_52_high", &["week52High"]),
+    ("week_52_low", &["week52Low"]),
+];
+
+/// Portfolio `view` parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PortfolioView {
+    #[default]
+    Quick,
+    Performance,
+    Fundamental,
+    #[serde(rename = "OPTIONSWATCH")]
+    OptionsWatch,
+    Complete,
+}
+
+impl PortfolioView {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortfolioView::Quick => "QUICK",
+            PortfolioView::Performance => "PERFORMANCE",
+            PortfolioView::Fundamental => "FUNDAMENTAL",
+            PortfolioView::OptionsWatch => "OPTIONSWATCH",
+            PortfolioView::Complete => "COMPLETE",
+        }
+    }
+}
+
+/// Portfolio `sortBy` parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PortfolioSortBy {
+    #[serde(rename = "SYMBOL")]
+    Symbol,
+    #[serde(rename = "TYPE_NAME")]
+    TypeName,
+    #[serde(rename = "EXCHANGE_NAME")]
+    ExchangeName,
+    #[serde(rename = "CURRENCY")]
+    Currency,
+    #[serde(rename = "QUANTITY")]
+    Quantity,
+    #[serde(rename = "LONG_OR_SHORT")]
+    LongOrShort,
+    #[serde(rename = "DATE_ACQUIRED")]
+    DateAcquired,
+    #[serde(rename = "PRICEPAID")]
+    PricePaid,
+    #[serde(rename = "TOTAL_GAIN")]
+    TotalGain,
+    #[serde(rename = "TOTAL_GAIN_PCT")]
+    TotalGainPct,
+    #[serde(rename = "MARKET_VALUE")]
+    MarketValue,
+    #[serde(rename = "BID")]
+    Bid,
+    #[serde(rename = "ASK")]
+    Ask,
+    #[serde(rename = "PRICE_CHANGE")]
+    PriceChange,
+    #[serde(rename = "PRICE_CHANGE_PCT")]
+    PriceChangePct,
+    #[serde(rename = "VOLUME")]
+    Volume,
+    #[serde(rename = "WEEK_52_HIGH")]
+    Week52High,
+    #[serde(rename = "WEEK_52_LOW")]
+    Week52Low,
+    #[serde(rename = "EPS")]
+    Eps,
+    #[serde(rename = "PE_RATIO")]
+    PeRatio,
+    #[serde(rename = "OPTION_TYPE")]
+    OptionType,
+    #[serde(rename = "STRIKE_PRICE")]
+    StrikePrice,
+    #[serde(rename = "PREMIUM")]
+    Premium,
+    #[serde(rename = "EXPIRATION")]
+    Expiration,
+    #[serde(rename = "DAYS_GAIN")]
+    DaysGain,
+    #[serde(rename = "DAYS_GAIN_PCT")]
+    DaysGainPct,
+    #[serde(rename = "COMMISSION")]
+    Commission,
+    #[serde(rename = "MARKETCAP")]
+    MarketCap,
+    #[serde(rename = "PREV_CLOSE")]
+    PrevClose,
+    #[serde(rename = "OPEN")]
+    Open,
+    #[serde(rename = "TOTAL_COST")]
+    TotalCost,
+    #[serde(rename = "PCT_OF_PORTFOLIO")]
+    PctOfPortfolio,
+    #[serde(rename = "LAST_TRADE")]
+    LastTrade,
+    #[serde(rename = "DIVIDEND")]
+    Dividend,
+    #[serde(rename = "DIV_YIELD")]
+    DivYield,
+}
+
+impl PortfolioSortBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PortfolioSortBy::Symbol => "SYMBOL",
+            PortfolioSortBy::TypeName => "TYPE_NAME",
+            PortfolioSortBy::ExchangeName => "EXCHANGE_NAME",
+            PortfolioSortBy::Currency => "CURRENCY",
+            PortfolioSortBy::Quantity => "QUANTITY",
+            PortfolioSortBy::LongOrShort => "LONG_OR_SHORT",
+            PortfolioSortBy::DateAcquired => "DATE_ACQUIRED",
+            PortfolioSortBy::PricePaid => "PRICEPAID",
+            PortfolioSortBy::TotalGain => "TOTAL_GAIN",
+            PortfolioSortBy::TotalGainPct => "TOTAL_GAIN_PCT",
+            PortfolioSortBy::MarketValue => "MARKET_VALUE",
+            PortfolioSortBy::Bid => "BID",
+            PortfolioSortBy::Ask => "ASK",
+            PortfolioSortBy::PriceChange => "PRICE_CHANGE",
+            PortfolioSortBy::PriceChangePct => "PRICE_CHANGE_PCT",
+            PortfolioSortBy::Volume => "VOLUME",
+            PortfolioSortBy::Week52High => "WEEK_52_HIGH",
+            PortfolioSortBy::Week52Low => "WEEK_52_LOW",
+            PortfolioSortBy::Eps => "EPS",
+            PortfolioSortBy::PeRatio => "PE_RATIO",
+            PortfolioSortBy::OptionType => "OPTION_TYPE",
+            PortfolioSortBy::StrikePrice => "STRIKE_PRICE",
+            PortfolioSortBy::Premium => "PREMIUM",
+            PortfolioSortBy::Expiration => "EXPIRATION",
+            PortfolioSortBy::DaysGain => "DAYS_GAIN",
+            PortfolioSortBy::DaysGainPct => "DAYS_GAIN_PCT",
+            PortfolioSortBy::Commission => "COMMISSION",
+            PortfolioSortBy::MarketCap => "MARKETCAP",
+            PortfolioSortBy::PrevClose => "PREV_CLOSE",
+            PortfolioSortBy::Open => "OPEN",
+            PortfolioSortBy::TotalCost => "TOTAL_COST",
+            PortfolioSortBy::PctOfPortfolio => "PCT_OF_PORTFOLIO",
+            PortfolioSortBy::LastTrade => "LAST_TRADE",
+            PortfolioSortBy::Dividend => "DIVIDEND",
+            PortfolioSortBy::DivYield => "DIV_YIELD",
+        }
+    }
+}
+
+/// Portfolio query options; all pages are always collected
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PortfolioQuery {
+    #[serde(default)]
+    pub view: PortfolioView,
+    /// E*TRADE `sortBy` field (e.g. `SYMBOL`, `MARKET_VALUE`)
+    #[serde(default)]
+    pub sort_by: Option<PortfolioSortBy>,
+    #[serde(default)]
+    pub sort_order: Option<SortOrder>,
+}
+
+impl PortfolioQuery {
+    pub fn to_query_string(&self, page_number: u32) -> String {
+        let mut params = vec![
+            format!("count={}", PORTFOLIO_PAGE_SIZE),
+            format!("pageNumber={}", page_number),
+            format!("view={}", self.view.as_str()),
+        ];
+        if let Some(sort_by) = self.sort_by {
+            params.push(format!("sortBy={}", sort_by.as_str()));
+        }
+        if let Some(sort_order) = self.sort_order {
+            params.push(format!("sortOrder={}", sort_order.as_str()));
+        }
+        params.join("&")
+    }
+}
+
+/// Asset class derived from the product `securityType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetClass {
+    Equity,
+    Option,
+    MutualFund,
+    MoneyMarket,
+    FixedIncome,
+    Other,
+}
+
+impl AssetClass {
+    pub fn from_security_type(security_type: Option<&str>) -> Self {
+        match security_type.map(|s| s.to_uppercase()).as_deref() {
+            None | Some("EQ") => AssetClass::Equity,
+            Some("OPTN") => AssetClass::Option,
+            Some("MF") => AssetClass::MutualFund,
+            Some("MMF") => AssetClass::MoneyMarket,
+            Some("BOND") | Some("FI") => AssetClass::FixedIncome,
+            Some(_) => AssetClass::Other,
+        }
+    }
+}
+
+/// Shares per standard equity option contract
+pub const DEFAULT_OPTION_MULTIPLIER: f64 = 100.0;
+
+/// A position plus E*TRADE-specific fields exposed as extensions
+#[derive(Debug, Clone)]
+pub struct PositionDetail {
+    pub position: Position,
+    pub asset_class: AssetClass,
+    /// Contract multiplier (1 for non-derivatives)
+    pub multiplier: f64,
+    pub position_id: Option<String>,
+    /// Absolute URL of the lots endpoint for this position
+    pub lots_url: Option<String>,
+    pub extensions: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LotTerm {
+    ShortTerm,
+    LongTerm,
+    Unknown,
+}
+
+/// Single tax lot of a position
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionLot {
+    pub position_id: Option<String>,
+    pub lot_id: Option<String>,
+    pub acquired_at: Option<DateTime<Utc>>,
+    pub original_quantity: Option<f64>,
+    pub remaining_quantity: f64,
+    pub price: Option<f64>,
+    pub total_cost: Option<f64>,
+    pub market_value: Option<f64>,
+    pub total_gain: Option<f64>,
+    pub total_gain_pct: Option<f64>,
+    pub term: LotTerm,
+}
+
+// --- E*TRADE wire format ---
+
+#[derive(Deserialize)]
+pub(crate) struct PortfolioResponse {
+    #[serde(rename = "PortfolioResponse")]
+    pub response: Option<PortfolioInner>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PortfolioInner {
+    #[serde(rename = "AccountPortfolio")]
+    pub account_portfolio: Option<Vec<AccountPortfolio>>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct AccountPortfolio {
+    #[serde(rename = "Position")]
+    pub position: Option<Vec<ETradePosition>>,
+    #[serde(rename = "totalPages")]
+    pub total_pages: Option<u32>,
+    #[serde(rename = "nextPageNo")]
+    pub next_page_no: Option<serde_json::Value>,
+}
+
+impl AccountPortfolio {
+    /// Next page number to request, if E*TRADE reports one
+    pub fn next_page(&self, current: u32) -> Option<u32> {
+        let next = match &self.next_page_no {
+            Some(serde_json::Value::Number(n)) => n.as_u64().map(|n| n as u32),
+            Some(serde_json::Value::String(s)) => s.parse().ok(),
+            _ => None,
+        };
+        match (next, self.total_pages) {
+            (Some(n), _) if n > current => Some(n),
+            (None, Some(total)) if current < total => Some(current + 1),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ETradePosition {
+    #[serde(rename = "positionId")]
+    pub position_id: Option<serde_json::Value>,
+    #[serde(rename = "Product")]
+    pub product: ProductInfo,
+    /// `LONG` or `SHORT`
+    #[serde(rename = "positionType")]
+    pub position_type: Option<String>,
+    pub quantity: Option<f64>,
+    #[serde(rename = "costPerShare")]
+    pub cost_per_share: Option<f64>,
+    #[serde(rename = "marketValue")]
+    pub market_value: Option<f64>,
+    #[serde(rename = "totalGain")]
+    pub total_gain: Option<f64>,
+    #[serde(rename = "totalGainPct")]
+    pub total_gain_pct: Option<f64>,
+    #[serde(rename = "daysGain")]
+    pub days_gain: Option<f64>,
+    #[serde(rename = "daysGainPct")]
+    pub days_gain_pct: Option<f64>,
+    #[serde(rename = "pctOfPortfolio")]
+    pub pct_of_portfolio: Option<f64>,
+    #[serde(rename = "lotsDetails")]
+    pub lots_details: Option<String>,
+    /// View blocks (`Quick`, `Performance`, ...) and other untyped fields
+    #[serde(flatten)]
+    pub blocks: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ProductInfo {
+    pub symbol: String,
+    #[serde(rename = "securityType")]
+    pub security_type: Option<String>,
+    #[serde(rename = "callPut")]
+    pub call_put: Option<String>,
+    #[serde(rename = "expiryYear")]
+    pub expiry_year: Option<i32>,
+    #[serde(rename = "expiryMonth")]
+    pub expiry_month: Option<u32>,
+    #[serde(rename = "expiryDay")]
+    pub expiry_day: Option<u32>,
+    #[serde(rename = "strikePrice")]
+    pub strike_price: Option<f64>,
+    #[serde(rename = "contractSize")]
+    pub contract_size: Option<f64>,
+}
+
+impl ProductInfo {
+    /// OCC/OSI option symbol: root padded to 6, YYMMDD, C/P, strike x 1000
+    /// padded to 8 digits (e.g. `AAPL  240119C00150000`)
+    pub fn osi_symbol(&self) -> Option<String> {
+        let call_put = match self.call_put.as_deref()?.to_uppercase().as_str() {
+            "CALL" | "C" => 'C',
+            "PUT" | "P" => 'P',
+            _ => return None,
+        };
+        let year = self.expiry_year?;
+        // E*TRADE sometimes reports a two-digit year
+        let year = if year < 100 { year } else { year % 100 };
+
+        Some(format!(
+            "{:<6}{:02}{:02}{:02}{}{:08}",
+            self.symbol.trim().to_uppercase(),
+            year,
+            self.expiry_month?,
+            self.expiry_day?,
+            call_put,
+            (self.strike_price? * 1000.0).round() as u64
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PositionLotsResponse {
+    #[serde(rename = "PositionLotsResponse")]
+    pub response: Option<PositionLotsInner>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PositionLotsInner {
+    #[serde(rename = "PositionLot")]
+    pub position_lot: Option<Vec<ETradePositionLot>>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ETradePositionLot {
+    #[serde(rename = "positionId")]
+    pub position_id: Option<serde_json::Value>,
+    #[serde(rename = "positionLotId")]
+    pub position_lot_id: Option<serde_json::Value>,
+    pub price: Option<f64>,
+    #[serde(rename = "originalQty")]
+    pub original_qty: Option<f64>,
+    #[serde(rename = "remainingQty")]
+    pub remaining_qty: Option<f64>,
+    #[serde(rename = "acquiredDate")]
+    pub acquired_date: Option<i64>,
+    #[serde(rename = "totalCost")]
+    pub total_cost: Option<f64>,
+    #[serde(rename = "marketValue")]
+    pub market_value: Option<f64>,
+    #[serde(rename = "totalGain")]
+    pub total_gain: Option<f64>,
+}
+
+impl From<ETradePosition> for PositionDetail {
+    fn from(pos: ETradePosition) -> Self {
+        let view_blocks: Vec<(&str, &serde_json::Value)> = VIEW_BLOCKS
+            .iter()
+            .filter_map(|name| pos.blocks.get(*name).map(|b| (*name, b)))
+            .collect();
+        let view_number = |field: &str| {
+            view_blocks
+                .iter()
+                .find_map(|(_, b)| b.get(field).and_then(|v| v.as_f64()))
+        };
+
+        let last_trade = view_number("lastTrade");
+        let current_price = last_trade.or(pos.cost_per_share).unwrap_or(0.0);
+        let position_id = pos.position_id.as_ref().map(value_to_string);
+
+        let product = &pos.product;
+        let asset_class = AssetClass::from_security_type(product.security_type.as_deref());
+        let osi_symbol = match asset_class {
+            AssetClass::Option => product.osi_symbol(),
+            _ => None,
+        };
+        let multiplier = match asset_class {
+            AssetClass::Option => product
+                .contract_size
+                .or_else(|| view_number("contractSize"))
+                .filter(|m| *m > 0.0)
+                .unwrap_or(DEFAULT_OPTION_MULTIPLIER),
+            _ => 1.0,
+        };
+
+        // Shorts (including written options) carry a negative quantity
+        let is_short = pos
+            .position_type
+            .as_deref()
+            .map(|t| t.eq_ignore_ascii_case("SHORT"))
+            .unwrap_or(false);
+        let quantity = pos.quantity.unwrap_or(0.0).abs();
+        let quantity = if is_short { -quantity } else { quantity };
+
+        // With a price, derive the gain from the signed quantity so shorts
+        // gain when the price falls; otherwise trust E*TRADE's totals
+        let (unrealized_pnl, unrealized_pnl_percent) = match (last_trade, pos.cost_per_share) {
+            (Some(price), Some(cost)) => (
+                (price - cost) * quantity * multiplier,
+                if cost != 0.0 {
+                    (price - cost) / cost * 100.0 * quantity.signum()
+                } else {
+                    0.0
+                },
+            ),
+            _ => (
+                pos.total_gain.unwrap_or(0.0),
+                pos.total_gain_pct.unwrap_or(0.0),
+            ),
+        };
+
+        let mut extensions = HashMap::new();
+        extensions.insert("asset_class".to_string(), serde_json::json!(asset_class));
+        extensions.insert(
+            "position_type".to_string(),
+            serde_json::json!(if is_short { "SHORT" } else { "LONG" }),
+        );
+        if is_short {
+            if let Some(mv) = pos.market_value {
+                extensions.insert(
+                    "short_market_value".to_string(),
+                    serde_json::json!(-mv.abs()),
+                );
+            }
+            if let Some(cost) = pos.cost_per_share {
+                extensions.insert(
+                    "short_proceeds".to_string(),
+                    serde_json::json!(cost * quantity.abs() * multiplier),
+                );
+            }
+            // The portfolio API does not report borrow rates or fees
+            extensions.insert("borrow_rate".to_string(), serde_json::Value::Null);
+        }
+        if let Some(t) = &product.security_type {
+            extensions.insert("security_type".to_string(), serde_json::json!(t));
+        }
+        if asset_class == AssetClass::Option {
+            extensions.insert(
+                "underlying_symbol".to_string(),
+                serde_json::json!(product.symbol),
+            );
+            extensions.insert("multiplier".to_string(), serde_json::json!(multiplier));
+            if let Some(cp) = &product.call_put {
+                extensions.insert("option_type".to_string(), serde_json::json!(cp));
+            }
+            if let Some(strike) = product.strike_price {
+                extensions.insert("strike_price".to_string(), serde_json::json!(strike));
+            }
+            if let (Some(y), Some(m), Some(d)) = (
+                product.expiry_year,
+                product.expiry_month,
+                product.expiry_day,
+            ) {
+                let y = if y < 100 { 2000 + y } else { y };
+                extensions.insert(
+                    "expiry_date".to_string(),
+                    serde_json::json!(format!("{:04}-{:02}-{:02}", y, m, d)),
+                );
+            }
+        }
+        if let Some(id) = &position_id {
+            extensions.insert(
+                "position_id".to_string(),
+                serde_json::Value::String(id.clone()),
+            );
+        }
+        if let Some(mv) = pos.market_value {
+            extensions.insert("market_value".to_string(), serde_json::json!(mv));
+        }
+        if let Some(pct) = pos.pct_of_portfolio {
+            extensions.insert("pct_of_portfolio".to_string(), serde_json::json!(pct));
+        }
+        for (key, fields) in VIEW_FIELDS {
+            if let Some(v) = fields.iter().find_map(|f| view_number(f)) {
+                extensions.insert(key.to_string(), serde_json::json!(v));
+            }
+        }
+        if let Some(v) = pos.days_gain {
+            extensions.insert("days_gain".to_string(), serde_json::json!(v));
+        }
+        if let Some(v) = pos.days_gain_pct {
+            extensions.insert("days_gain_pct".to_string(), serde_json::json!(v));
+        }
+        if let Some(range) = view_blocks
+            .iter()
+            .find_map(|(_, b)| b.get("week52Range").and_then(|v| v.as_str()))
+        {
+            extensions.insert(
+                "week_52_range".to_string(),
+                serde_json::Value::String(range.to_string()),
+            );
+        }
+        // Keep the raw view blocks so nothing E*TRADE sends is lost
+        for (name, block) in &view_blocks {
+            extensions.insert(format!("view_{}", name.to_lowercase()), (*block).clone());
+        }
+
+        PositionDetail {
+            position: Position {
+                symbol_id: osi_symbol.unwrap_or_else(|| pos.product.symbol.clone()),
+                quantity,
+                average_price: pos.cost_per_share.unwrap_or(0.0),
+                current_price,
+                unrealized_pnl,
+                unrealized_pnl_percent,
+            },
+            asset_class,
+            multiplier,
+            position_id,
+            lots_url: pos.lots_details,
+            extensions,
+        }
+    }
+}
+
+impl From<ETradePositionLot> for PositionLot {
+    fn from(lot: ETradePositionLot) -> Self {
+        let acquired_at = lot.acquired_date.and_then(millis_to_utc);
+        let term = match acquired_at {
+            Some(a) if (Utc::now() - a).num_days() > LONG_TERM_DAYS => LotTerm::LongTerm,
+            Some(_) => LotTerm::ShortTerm,
+            None => LotTerm::Unknown,
+        };
+        let total_gain_pct = match (lot.total_gain, lot.total_cost) {
+            (Some(g), Some(c)) if c != 0.0 => Some(g / c * 100.0),
+            _ => None,
+        };
+
+        PositionLot {
+            position_id: lot.position_id.as_ref().map(value_to_string),
+            lot_id: lot.position_lot_id.as_ref().map(value_to_string),
+            acquired_at,
+            original_quantity: lot.original_qty,
+            remaining_quantity: lot.remaining_qty.unwrap_or(0.0),
+            price: lot.price,
+            total_cost: lot.total_cost,
+            market_value: lot.market_value,
+            total_gain: lot.total_gain,
+            total_gain_pct,
+            term,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_string_uses_validated_sort_params() {
+        let query: PortfolioQuery = serde_json::from_value(serde_json::json!({
+            "view": "PERFORMANCE",
+            "sort_by": "MARKET_VALUE",
+            "sort_order": "ASC"
+        }))
+        .unwrap();
+        assert_eq!(
+            query.to_query_string(2),
+            "count=100&pageNumber=2&view=PERFORMANCE&sortBy=MARKET_VALUE&sortOrder=ASC"
+        );
+    }
+
+    fn position(json: serde_json::Value) -> PositionDetail {
+        serde_json::from_value::<ETradePosition>(json)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn short_position_gains_when_price_falls() {
+        let detail = position(serde_json::json!({
+            "positionId": 1,
+            "Product": { "symbol": "TSLA", "securityType": "EQ" },
+            "positionType": "SHORT",
+            "quantity": 10,
+            "costPerShare": 50.0,
+            "totalGain": -100.0,
+            "Quick": { "lastTrade": 40.0 }
+        }));
+        assert_eq!(detail.position.quantity, -10.0);
+        assert_eq!(detail.position.unrealized_pnl, 100.0);
+        assert_eq!(detail.position.unrealized_pnl_percent, 20.0);
+        assert_eq!(detail.extensions["position_type"], "SHORT");
+        assert!(detail.extensions["borrow_rate"].is_null());
+    }
+
+    #[test]
+    fn long_position_loses_when_price_falls() {
+        let detail = position(serde_json::json!({
+            "positionId": 2,
+            "Product": { "symbol": "TSLA", "securityType": "EQ" },
+            "positionType": "LONG",
+            "quantity": 10,
+            "costPerShare": 50.0,
+            "Quick": { "lastTrade": 40.0 }
+        }));
+        assert_eq!(detail.position.unrealized_pnl, -100.0);
+        assert_eq!(detail.position.unrealized_pnl_percent, -20.0);
+        assert!(!detail.extensions.contains_key("borrow_rate"));
+    }
+
+    #[test]
+    fn short_option_applies_multiplier() {
+        let detail = position(serde_json::json!({
+            "Product": {
+                "symbol": "AAPL",
+                "securityType": "OPTN",
+                "callPut": "CALL",
+                "expiryYear": 2024,
+                "expiryMonth": 1,
+                "expiryDay": 19,
+                "strikePrice": 150.0
+            },
+            "positionType": "SHORT",
+            "quantity": 2,
+            "costPerShare": 3.0,
+            "Quick": { "lastTrade": 5.0 }
+        }));
+        assert_eq!(detail.position.symbol_id, "AAPL  240119C00150000");
+        assert_eq!(detail.position.unrealized_pnl, -400.0);
+    }
+
+    #[test]
+    fn falls_back_to_total_gain_without_price() {
+        let detail = position(serde_json::json!({
+            "Product": { "symbol": "TSLA", "securityType": "EQ" },
+            "positionType": "SHORT",
+            "quantity": 10,
+            "costPerShare": 50.0,
+            "totalGain": 75.0,
+            "totalGainPct": 15.0
+        }));
+        assert_eq!(detail.position.unrealized_pnl, 75.0);
+        assert_eq!(detail.position.unrealized_pnl_percent, 15.0);
+    }
+
+    #[test]
+    fn rejects_unknown_sort_params() {
+        let sort_by = serde_json::json!({ "sort_by": "SYMBOL&view=COMPLETE" });
+        assert!(serde_json::from_value::<PortfolioQuery>(sort_by).is_err());
+        let sort_order = serde_json::json!({ "sort_order": "sideways" });
+        assert!(serde_json::from_value::<PortfolioQuery>(sort_order).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Short-lived quote cache
+//!
+//! Keeps recent quotes keyed by symbol and detail level so that strategies
+//! polling the same symbols do not burn E*TRADE rate limit.
+
+use crate::error::PluginError;
+use crate::etrade::{ETradeClient, Quote, QuoteDetail, MAX_QUOTE_SYMBOLS};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+
+/// Default time-to-live for cached quotes
+pub const DEFAULT_QUOTE_TTL_MS: i64 = 5000;
+
+struct CachedQuote {
+    quote: Quote,
+    fetched_at_ms: i64,
+}
+
+pub struct QuoteCache {
+    ttl_ms: i64,
+    entries: HashMap<(String, QuoteDetail), CachedQuote>,
+}
+
+impl QuoteCache {
+    pub fn new(ttl_ms: i64) -> Self {
+        Self {
+            ttl_ms,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn set_ttl(&mut self, ttl_ms: i64) {
+        self.ttl_ms = ttl_ms;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Return quotes for `symbols`, fetching only those missing or expired.
+    ///
+    /// Quotes are returned in the order requested; symbols E*TRADE does not
+    /// recognize are omitted.
+    pub fn get_quotes(
+        &mut self,
+        client: &ETradeClient,
+        symbols: &[String],
+        detail: QuoteDetail,
+        force_refresh: bool,
+    ) -> Result<Vec<Quote>, PluginError> {
+        let now = Utc::now().timestamp_millis();
+
+        let symbols = normalize_symbols(symbols);
+
+        let stale: Vec<String> = symbols
+            .iter()
+            .filter(|s| force_refresh || !self.is_fresh(s, detail, now))
+            .cloned()
+            .collect();
+
+        for chunk in stale.chunks(MAX_QUOTE_SYMBOLS) {
+            for quote in client.get_quotes(chunk, detail)? {
+                self.entries.insert(
+                    (quote.symbol.to_uppercase(), detail),
+                    CachedQuote {
+                        quote,
+                        fetched_at_ms: now,
+                    },
+                );
+            }
+        }
+
+        Ok(symbols
+            .iter()
+            .filter_map(|s| self.entries.get(&(s.clone(), detail)))
+            .map(|c| c.quote.clone())
+            .collect())
+    }
+
+    fn is_fresh(&self, symbol: &str, detail: QuoteDetail, now: i64) -> bool {
+        self.entries
+            .get(&(symbol.to_string(), detail))
+            .map(|c| now - c.fetched_at_ms < self.ttl_ms)
+            .unwrap_or(false)
+    }
+}
+
+/// Trim and upper-case symbols, dropping blanks and repeats while keeping
+/// the first occurrence of each in request order
+fn normalize_symbols(symbols: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    symbols
+        .iter()
+        .map(|s| s.trim().to_uppercase())
+        .filter(|s| !s.is_empty() && seen.insert(s.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn normalize_symbols_removes_non_adjacent_duplicates() {
+        assert_eq!(
+            normalize_symbols(&strings(&["AAPL", "MSFT", "AAPL"])),
+            strings(&["AAPL", "MSFT"])
+        );
+        assert_eq!(
+            normalize_symbols(&strings(&[" msft", "", "aapl ", "MSFT", "  "])),
+            strings(&["MSFT", "AAPL"])
+        );
+    }
+}
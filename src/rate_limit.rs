@@ -0,0 +1,333 @@
+//! Client-side rate limiting per E*TRADE endpoint family
+//!
+//! E*TRADE throttles the accounts, orders and market APIs independently,
+//! with both a per-second and a per-hour limit. Each family gets a pair of
+//! token buckets; a request waits for a token (up to `max_wait_ms`) instead
+//! of being sent and rejected with 429.
+
+use crate::common::now_ms;
+use crate::error::PluginError;
+use crate::timeouts::Operation;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Longest a request may queue for a token before failing as rate limited
+pub const DEFAULT_RATE_LIMIT_MAX_WAIT_MS: i64 = 10000;
+
+const SECOND_MS: i64 = 1000;
+const HOUR_MS: i64 = 3_600_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointFamily {
+    Accounts,
+    Orders,
+    Market,
+}
+
+impl EndpointFamily {
+    pub const ALL: [EndpointFamily; 3] = [
+        EndpointFamily::Accounts,
+        EndpointFamily::Orders,
+        EndpointFamily::Market,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndpointFamily::Accounts => "accounts",
+            EndpointFamily::Orders => "orders",
+            EndpointFamily::Market => "market",
+        }
+    }
+
+    /// Published E*TRADE limits as (per second, per hour)
+    fn default_limits(&self) -> (u32, u32) {
+        match self {
+            EndpointFamily::Accounts => (2, 7000),
+            EndpointFamily::Orders => (2, 7000),
+            EndpointFamily::Market => (4, 14000),
+        }
+    }
+}
+
+/// Paths are classified once, by `Operation::from_path`
+impl From<Operation> for EndpointFamily {
+    fn from(operation: Operation) -> Self {
+        match operation {
+            Operation::Market => EndpointFamily::Market,
+            Operation::Order => EndpointFamily::Orders,
+            // OAuth token renewal counts against the accounts limits
+            Operation::Auth | Operation::Account => EndpointFamily::Accounts,
+        }
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_ms: f64,
+    updated_ms: i64,
+}
+
+impl TokenBucket {
+    fn new(capacity: u32, window_ms: i64, now_ms: i64) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_ms: capacity / window_ms as f64,
+            updated_ms: now_ms,
+        }
+    }
+
+    fn refill(&mut self, now_ms: i64) {
+        let elapsed = (now_ms - self.updated_ms).max(0) as f64;
+        self.tokens = (self.tokens + elapsed * self.refill_per_ms).min(self.capacity);
+        self.updated_ms = now_ms;
+    }
+
+    /// Milliseconds until one token is available
+    fn wait_ms(&self) -> i64 {
+        if self.tokens >= 1.0 {
+            0
+        } else {
+            ((1.0 - self.tokens) / self.refill_per_ms).ceil() as i64
+        }
+    }
+}
+
+struct FamilyBuckets {
+    per_second: TokenBucket,
+    per_hour: TokenBucket,
+}
+
+impl FamilyBuckets {
+    fn refill(&mut self, now_ms: i64) {
+        self.per_second.refill(now_ms);
+        self.per_hour.refill(now_ms);
+    }
+
+    fn wait_ms(&self) -> i64 {
+        self.per_second.wait_ms().max(self.per_hour.wait_ms())
+    }
+}
+
+/// Remaining budget of one endpoint family
+#[derive(Debug, Clone, Serialize)]
+pub struct RateLimitStatus {
+    pub family: EndpointFamily,
+    pub per_second_limit: u32,
+    pub per_second_remaining: u32,
+    pub per_hour_limit: u32,
+    pub per_hour_remaining: u32,
+    /// Time until the next request can be sent without queueing
+    pub wait_ms: i64,
+}
+
+pub struct RateLimiter {
+    max_wait_ms: i64,
+    buckets: HashMap<EndpointFamily, FamilyBuckets>,
+    /// Current time in epoch milliseconds
+    clock: fn() -> i64,
+    /// Waits for a token to refill
+    sleep: fn(Duration),
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::from_config(&serde_json::Value::Null)
+    }
+}
+
+impl RateLimiter {
+    /// Read `rate_limit_{accounts,orders,market}_per_{second,hour}` and
+    /// `rate_limit_max_wait_ms` from the `initialize` config. A negative
+    /// wait is treated as `0` (fail instead of queueing).
+    pub fn from_config(config: &serde_json::Value) -> Self {
+        let now = now_ms();
+        let get_u32 = |key: String| {
+            config
+                .get(&key)
+                .and_then(|v| v.as_u64())
+                .map(|n| n.min(u32::MAX as u64) as u32)
+        };
+
+        let buckets = EndpointFamily::ALL
+            .iter()
+            .map(|family| {
+                let (per_second, per_hour) = family.default_limits();
+                let name = family.as_str();
+                let per_second =
+                    get_u32(format!("rate_limit_{}_per_second", name)).unwrap_or(per_second);
+                let per_hour = get_u32(format!("rate_limit_{}_per_hour", name)).unwrap_or(per_hour);
+
+                (
+                    *family,
+                    FamilyBuckets {
+                        per_second: TokenBucket::new(per_second, SECOND_MS, now),
+                        per_hour: TokenBucket::new(per_hour, HOUR_MS, now),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            max_wait_ms: config
+                .get("rate_limit_max_wait_ms")
+                .and_then(|v| v.as_i64())
+                .map(|ms| ms.max(0))
+                .unwrap_or(DEFAULT_RATE_LIMIT_MAX_WAIT_MS),
+            buckets,
+            clock: now_ms,
+            sleep: std::thread::sleep,
+        }
+    }
+
+    /// Take a token for `family`, sleeping until one is available. Fails
+    /// without sending if the wait would exceed `max_wait_ms`.
+    pub fn acquire(&mut self, family: EndpointFamily) -> Result<(), PluginError> {
+        let buckets = match self.buckets.get_mut(&family) {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        buckets.refill((self.clock)());
+        let wait_ms = buckets.wait_ms();
+        if wait_ms > self.max_wait_ms {
+            return Err(PluginError::RateLimited {
+                message: format!(
+                    "Client-side {} rate limit exhausted, next request in {}ms",
+                    family.as_str(),
+                    wait_ms
+                ),
+                retry_after_secs: Some((wait_ms as u64).div_ceil(1000)),
+            });
+        }
+        if wait_ms > 0 {
+            (self.sleep)(Duration::from_millis(wait_ms as u64));
+            buckets.refill((self.clock)());
+        }
+
+        buckets.per_second.tokens = (buckets.per_second.tokens - 1.0).max(0.0);
+        buckets.per_hour.tokens = (buckets.per_hour.tokens - 1.0).max(0.0);
+        Ok(())
+    }
+
+    pub fn status(&mut self) -> Vec<RateLimitStatus> {
+        let now_ms = (self.clock)();
+        EndpointFamily::ALL
+            .iter()
+            .filter_map(|family| {
+                let buckets = self.buckets.get_mut(family)?;
+                buckets.refill(now_ms);
+                Some(RateLimitStatus {
+                    family: *family,
+                    per_second_limit: buckets.per_second.capacity as u32,
+                    per_second_remaining: buckets.per_second.tokens.floor() as u32,
+                    per_hour_limit: buckets.per_hour.capacity as u32,
+                    per_hour_remaining: buckets.per_hour.tokens.floor() as u32,
+                    wait_ms: buckets.wait_ms(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        static NOW_MS: Cell<i64> = const { Cell::new(0) };
+        static SLEPT_MS: Cell<u128> = const { Cell::new(0) };
+    }
+
+    fn advance(ms: i64) {
+        NOW_MS.with(|now| now.set(now.get() + ms));
+    }
+
+    /// Limiter on a fake clock that sleeping advances
+    fn limiter(config: serde_json::Value) -> RateLimiter {
+        let mut limiter = RateLimiter::from_config(&config);
+        limiter.clock = || NOW_MS.with(Cell::get);
+        limiter.sleep = |delay| {
+            SLEPT_MS.with(|slept| slept.set(slept.get() + delay.as_millis()));
+            advance(delay.as_millis() as i64);
+        };
+        limiter
+    }
+
+    #[test]
+    fn refills_tokens_over_time() {
+        let mut limiter = limiter(serde_json::json!({
+            "rate_limit_accounts_per_second": 2,
+            "rate_limit_max_wait_ms": 0
+        }));
+
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_err());
+
+        // Two tokens per second: one is back after 500ms
+        advance(500);
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_err());
+    }
+
+    #[test]
+    fn waits_for_a_token_within_max_wait() {
+        let mut limiter = limiter(serde_json::json!({
+            "rate_limit_market_per_second": 1
+        }));
+
+        assert!(limiter.acquire(EndpointFamily::Market).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Market).is_ok());
+        assert_eq!(SLEPT_MS.with(Cell::get), 1000);
+    }
+
+    #[test]
+    fn rejects_waits_longer_than_max_wait() {
+        let mut limiter = limiter(serde_json::json!({
+            "rate_limit_orders_per_second": 1,
+            "rate_limit_max_wait_ms": 100
+        }));
+
+        assert!(limiter.acquire(EndpointFamily::Orders).is_ok());
+        match limiter.acquire(EndpointFamily::Orders) {
+            Err(PluginError::RateLimited {
+                retry_after_secs, ..
+            }) => assert_eq!(retry_after_secs, Some(1)),
+            other => panic!("expected rate_limited, got {:?}", other),
+        }
+        assert_eq!(SLEPT_MS.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn families_have_separate_buckets() {
+        let mut limiter = limiter(serde_json::json!({
+            "rate_limit_market_per_second": 1,
+            "rate_limit_max_wait_ms": 0
+        }));
+
+        assert!(limiter.acquire(EndpointFamily::Market).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Market).is_err());
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_ok());
+        assert!(limiter.acquire(EndpointFamily::Orders).is_ok());
+
+        let status = limiter.status();
+        assert_eq!(status[0].per_second_remaining, 1);
+        assert_eq!(status[2].per_second_remaining, 0);
+    }
+
+    #[test]
+    fn negative_max_wait_is_clamped_to_zero() {
+        let mut limiter = limiter(serde_json::json!({
+            "rate_limit_max_wait_ms": -1
+        }));
+
+        assert_eq!(limiter.max_wait_ms, 0);
+        assert!(limiter.acquire(EndpointFamily::Accounts).is_ok());
+    }
+}
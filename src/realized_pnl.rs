@@ -0,0 +1,607 @@
+//! Realized P&L from executed trades
+//!
+//! Replays buy/sell transactions in date order, relieving open lots with the
+//! selected method. Dividend reinvestments open lots like buys. E*TRADE
+//! relieves lots FIFO unless the account holder picks another default, which
+//! the API does not report, so `BrokerDefault` is FIFO.
+//!
+//! Only long lots are tracked. Short sales, written options and the trades
+//! that close them are left out of the report with a warning.
+
+use crate::common::LONG_TERM_DAYS;
+use crate::transactions::{Transaction, TransactionType};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const QTY_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LotMethod {
+    /// Lot relief method E*TRADE applies by default (FIFO)
+    #[default]
+    BrokerDefault,
+    Fifo,
+    Lifo,
+    HighestCost,
+    SpecificLot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodGranularity {
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl PeriodGranularity {
+    fn label(&self, date: DateTime<Utc>) -> String {
+        match self {
+            PeriodGranularity::Month => date.format("%Y-%m").to_string(),
+            PeriodGranularity::Quarter => format!("{}-Q{}", date.year(), date.month0() / 3 + 1),
+            PeriodGranularity::Year => date.year().to_string(),
+        }
+    }
+}
+
+/// Lots chosen for one sell when using `LotMethod::SpecificLot`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpecificLotSelection {
+    pub sell_transaction_id: String,
+    pub lots: Vec<LotQuantity>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LotQuantity {
+    pub buy_transaction_id: String,
+    pub quantity: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RealizedLot {
+    pub buy_transaction_id: String,
+    pub acquired_at: Option<DateTime<Utc>>,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    pub long_term: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RealizedTrade {
+    pub symbol: String,
+    pub sell_transaction_id: String,
+    pub sold_at: Option<DateTime<Utc>>,
+    pub quantity: f64,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    pub lots: Vec<RealizedLot>,
+    /// Quantity sold with no matching open lot (e.g. bought before history)
+    pub unmatched_quantity: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RealizedSummary {
+    pub quantity: f64,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    pub short_term_pnl: f64,
+    pub long_term_pnl: f64,
+}
+
+impl RealizedSummary {
+    fn add(&mut self, trade: &RealizedTrade) {
+        self.quantity += trade.quantity;
+        self.proceeds += trade.proceeds;
+        self.cost_basis += trade.cost_basis;
+        self.realized_pnl += trade.realized_pnl;
+
+        // Split proceeds across lots pro rata to attribute term
+        for lot in &trade.lots {
+            let lot_proceeds = trade.proceeds * lot.quantity / trade.quantity;
+            let pnl = lot_proceeds - lot.cost_basis;
+            if lot.long_term {
+                self.long_term_pnl += pnl;
+            } else {
+                self.short_term_pnl += pnl;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RealizedPnlReport {
+    pub method: LotMethod,
+    pub trades: Vec<RealizedTrade>,
+    pub by_symbol: BTreeMap<String, RealizedSummary>,
+    pub by_period: BTreeMap<String, RealizedSummary>,
+    pub total: RealizedSummary,
+    pub warnings: Vec<String>,
+}
+
+struct OpenLot {
+    buy_transaction_id: String,
+    acquired_at: Option<DateTime<Utc>>,
+    quantity: f64,
+    cost_per_share: f64,
+}
+
+/// Compute realized P&L for sells between `start_date` and `end_date`.
+///
+/// `transactions` should start early enough to contain the buys that opened
+/// the lots being sold; earlier sells are replayed but not reported.
+pub fn compute_realized_pnl(
+    transactions: &[Transaction],
+    method: LotMethod,
+    granularity: PeriodGranularity,
+    specific_lots: &[SpecificLotSelection],
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+) -> RealizedPnlReport {
+    let mut trades: Vec<&Transaction> = transactions
+        .iter()
+        .filter(|t| {
+            matches!(
+                t.transaction_type,
+                TransactionType::Buy
+                    | TransactionType::Reinvestment
+                    | TransactionType::Sell
+                    | TransactionType::SellShort
+                    | TransactionType::BuyToCover
+            ) && t.symbol.is_some()
+                && t.quantity.map(|q| q.abs() > QTY_EPSILON).unwrap_or(false)
+        })
+        .collect();
+    trades.sort_by_key(|t| t.transaction_date.or(t.post_date));
+
+    let selections: HashMap<&str, &SpecificLotSelection> = specific_lots
+        .iter()
+        .map(|s| (s.sell_transaction_id.as_str(), s))
+        .collect();
+
+    let mut open: HashMap<String, Vec<OpenLot>> = HashMap::new();
+    let mut report = RealizedPnlReport {
+        method,
+        trades: vec![],
+        by_symbol: BTreeMap::new(),
+        by_period: BTreeMap::new(),
+        total: RealizedSummary::default(),
+        warnings: vec![],
+    };
+
+    for tx in trades {
+        let symbol = tx.symbol.clone().unwrap_or_default().to_uppercase();
+        let quantity = tx.quantity.unwrap_or(0.0).abs();
+        let date = tx.transaction_date.or(tx.post_date);
+        let in_range = date
+            .map(|d| {
+                let d = d.date_naive();
+                start_date.map(|s| d >= s).unwrap_or(true)
+                    && end_date.map(|e| d <= e).unwrap_or(true)
+            })
+            .unwrap_or(true);
+
+        if matches!(
+            tx.transaction_type,
+            TransactionType::SellShort | TransactionType::BuyToCover
+        ) {
+            if in_range {
+                report.warnings.push(format!(
+                    "Skipped {} of {} {}: short positions are not tracked",
+                    tx.id, quantity, symbol
+                ));
+            }
+            continue;
+        }
+
+        if tx.transaction_type != TransactionType::Sell {
+            let total_cost = if tx.amount != 0.0 {
+                tx.amount.abs()
+            } else {
+                tx.price.unwrap_or(0.0) * quantity + tx.fee.unwrap_or(0.0)
+            };
+            open.entry(symbol).or_default().push(OpenLot {
+                buy_transaction_id: tx.id.clone(),
+                acquired_at: date,
+                quantity,
+                cost_per_share: total_cost / quantity,
+            });
+            continue;
+        }
+
+        let proceeds = if tx.amount != 0.0 {
+            tx.amount.abs()
+        } else {
+            tx.price.unwrap_or(0.0) * quantity - tx.fee.unwrap_or(0.0)
+        };
+
+        let lots = open.entry(symbol.clone()).or_default();
+        let (relieved, unmatched) = match method {
+            LotMethod::SpecificLot => match selections.get(tx.id.as_str()) {
+                Some(sel) => relieve_specific(lots, sel, quantity, &mut report.warnings),
+                None => {
+                    report.warnings.push(format!(
+                        "No lot selection for sell {} ({}); used FIFO",
+                        tx.id, symbol
+                    ));
+                    relieve_ordered(lots, LotMethod::Fifo, quantity)
+                }
+            },
+            m => relieve_ordered(lots, m, quantity),
+        };
+        lots.retain(|l| l.quantity > QTY_EPSILON);
+
+        if !in_range {
+            continue;
+        }
+
+        if unmatched > QTY_EPSILON {
+            report.warnings.push(format!(
+                "Sell {} of {} {} has {} shares without an open lot; cost basis treated as 0",
+                tx.id, quantity, symbol, unmatched
+            ));
+        }
+
+        let realized_lots: Vec<RealizedLot> = relieved
+            .into_iter()
+            .map(|(lot_id, acquired_at, qty, cost_per_share)| RealizedLot {
+                buy_transaction_id: lot_id,
+                acquired_at,
+                quantity: qty,
+                cost_basis: qty * cost_per_share,
+                long_term: match (acquired_at, date) {
+                    (Some(a), Some(s)) => (s - a).num_days() > LONG_TERM_DAYS,
+                    _ => false,
+                },
+            })
+            .collect();
+        let cost_basis: f64 = realized_lots.iter().map(|l| l.cost_basis).sum();
+
+        let trade = RealizedTrade {
+            symbol: symbol.clone(),
+            sell_transaction_id: tx.id.clone(),
+            sold_at: date,
+            quantity,
+            proceeds,
+            cost_basis,
+            realized_pnl: proceeds - cost_basis,
+            lots: realized_lots,
+            unmatched_quantity: unmatched,
+        };
+
+        report.by_symbol.entry(symbol).or_default().add(&trade);
+        if let Some(d) = date {
+            report
+                .by_period
+                .entry(granularity.label(d))
+                .or_default()
+                .add(&trade);
+        }
+        report.total.add(&trade);
+        report.trades.push(trade);
+    }
+
+    report
+}
+
+type Relieved = Vec<(String, Option<DateTime<Utc>>, f64, f64)>;
+
+fn relieve_ordered(lots: &mut [OpenLot], method: LotMethod, quantity: f64) -> (Relieved, f64) {
+    let mut order: Vec<usize> = (0..lots.len()).collect();
+    match method {
+        LotMethod::Lifo => order.reverse(),
+        LotMethod::HighestCost => order.sort_by(|a, b| {
+            lots[*b]
+                .cost_per_share
+                .partial_cmp(&lots[*a].cost_per_share)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        }),
+        _ => {}
+    }
+
+    let mut remaining = quantity;
+    let mut relieved = Vec::new();
+    for i in order {
+        if remaining <= QTY_EPSILON {
+            break;
+        }
+        let lot = &mut lots[i];
+        let take = lot.quantity.min(remaining);
+        lot.quantity -= take;
+        remaining -= take;
+        relieved.push((
+            lot.buy_transaction_id.clone(),
+            lot.acquired_at,
+            take,
+            lot.cost_per_share,
+        ));
+    }
+
+    (relieved, remaining.max(0.0))
+}
+
+fn relieve_specific(
+    lots: &mut [OpenLot],
+    selection: &SpecificLotSelection,
+    quantity: f64,
+    warnings: &mut Vec<String>,
+) -> (Relieved, f64) {
+    let mut remaining = quantity;
+    let mut relieved = Vec::new();
+
+    for pick in &selection.lots {
+        if remaining <= QTY_EPSILON {
+            break;
+        }
+        match lots
+            .iter_mut()
+            .find(|l| l.buy_transaction_id == pick.buy_transaction_id)
+        {
+            Some(lot) => {
+                let take = lot.quantity.min(pick.quantity).min(remaining);
+                lot.quantity -= take;
+                remaining -= take;
+                relieved.push((
+                    lot.buy_transaction_id.clone(),
+                    lot.acquired_at,
+                    take,
+                    lot.cost_per_share,
+                ));
+            }
+            None => warnings.push(format!(
+                "Lot {} selected for sell {} is not open",
+                pick.buy_transaction_id, selection.sell_transaction_id
+            )),
+        }
+    }
+
+    if remaining > QTY_EPSILON {
+        warnings.push(format!(
+            "Lot selection for sell {} covers {} of {} shares; used FIFO for the rest",
+            selection.sell_transaction_id,
+            quantity - remaining,
+            quantity
+        ));
+        let (rest, unmatched) = relieve_ordered(lots, LotMethod::Fifo, remaining);
+        relieved.extend(rest);
+        remaining = unmatched;
+    }
+
+    (relieved, remaining.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn tx(
+        id: &str,
+        transaction_type: TransactionType,
+        date: (i32, u32, u32),
+        quantity: f64,
+        price: f64,
+    ) -> Transaction {
+        let amount = match transaction_type {
+            TransactionType::Sell | TransactionType::SellShort => quantity * price,
+            _ => -quantity * price,
+        };
+        Transaction {
+            id: id.to_string(),
+            account_id: "1".to_string(),
+            transaction_type,
+            raw_type: String::new(),
+            transaction_date: Utc
+                .with_ymd_and_hms(date.0, date.1, date.2, 0, 0, 0)
+                .single(),
+            post_date: None,
+            amount,
+            description: String::new(),
+            symbol: Some("AAPL".to_string()),
+            security_type: Some("EQ".to_string()),
+            quantity: Some(quantity),
+            price: Some(price),
+            fee: None,
+            memo: None,
+        }
+    }
+
+    /// Three buys at 10, 30 and 20, then a sale of 15 shares for 600
+    fn history() -> Vec<Transaction> {
+        vec![
+            tx("b1", TransactionType::Buy, (2024, 1, 2), 10.0, 10.0),
+            tx("b2", TransactionType::Buy, (2024, 2, 1), 10.0, 30.0),
+            tx("b3", TransactionType::Buy, (2024, 3, 1), 10.0, 20.0),
+            tx("s1", TransactionType::Sell, (2024, 4, 1), 15.0, 40.0),
+        ]
+    }
+
+    fn compute(
+        transactions: &[Transaction],
+        method: LotMethod,
+        specific_lots: &[SpecificLotSelection],
+    ) -> RealizedPnlReport {
+        compute_realized_pnl(
+            transactions,
+            method,
+            PeriodGranularity::Month,
+            specific_lots,
+            None,
+            None,
+        )
+    }
+
+    fn relieved(report: &RealizedPnlReport) -> Vec<(&str, f64)> {
+        report.trades[0]
+            .lots
+            .iter()
+            .map(|l| (l.buy_transaction_id.as_str(), l.quantity))
+            .collect()
+    }
+
+    #[test]
+    fn fifo_relieves_oldest_lots_first() {
+        let report = compute(&history(), LotMethod::Fifo, &[]);
+        assert_eq!(relieved(&report), vec![("b1", 10.0), ("b2", 5.0)]);
+        assert_eq!(report.total.cost_basis, 250.0);
+        assert_eq!(report.total.realized_pnl, 350.0);
+    }
+
+    #[test]
+    fn broker_default_is_fifo() {
+        let report = compute(&history(), LotMethod::BrokerDefault, &[]);
+        assert_eq!(relieved(&report), vec![("b1", 10.0), ("b2", 5.0)]);
+    }
+
+    #[test]
+    fn lifo_relieves_newest_lots_first() {
+        let report = compute(&history(), LotMethod::Lifo, &[]);
+        assert_eq!(relieved(&report), vec![("b3", 10.0), ("b2", 5.0)]);
+        assert_eq!(report.total.realized_pnl, 250.0);
+    }
+
+    #[test]
+    fn highest_cost_relieves_most_expensive_lots_first() {
+        let report = compute(&history(), LotMethod::HighestCost, &[]);
+        assert_eq!(relieved(&report), vec![("b2", 10.0), ("b3", 5.0)]);
+        assert_eq!(report.total.realized_pnl, 200.0);
+    }
+
+    #[test]
+    fn specific_lot_relieves_selected_lots() {
+        let selection = SpecificLotSelection {
+            sell_transaction_id: "s1".to_string(),
+            lots: vec![
+                LotQuantity {
+                    buy_transaction_id: "b3".to_string(),
+                    quantity: 10.0,
+                },
+                LotQuantity {
+                    buy_transaction_id: "b1".to_string(),
+                    quantity: 5.0,
+                },
+            ],
+        };
+        let report = compute(&history(), LotMethod::SpecificLot, &[selection]);
+        assert_eq!(relieved(&report), vec![("b3", 10.0), ("b1", 5.0)]);
+        assert_eq!(report.total.realized_pnl, 350.0);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn specific_lot_falls_back_to_fifo_for_the_remainder() {
+        let selection = SpecificLotSelection {
+            sell_transaction_id: "s1".to_string(),
+            lots: vec![LotQuantity {
+                buy_transaction_id: "b3".to_string(),
+                quantity: 5.0,
+            }],
+        };
+        let report = compute(&history(), LotMethod::SpecificLot, &[selection]);
+        assert_eq!(relieved(&report), vec![("b3", 5.0), ("b1", 10.0)]);
+        assert_eq!(report.trades[0].unmatched_quantity, 0.0);
+        assert_eq!(report.total.cost_basis, 200.0);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn reinvestments_open_lots() {
+        let transactions = vec![
+            tx("r1", TransactionType::Reinvestment, (2024, 1, 2), 2.0, 15.0),
+            tx("s1", TransactionType::Sell, (2024, 4, 1), 2.0, 40.0),
+        ];
+        let report = compute(&transactions, LotMethod::Fifo, &[]);
+        assert_eq!(relieved(&report), vec![("r1", 2.0)]);
+        assert_eq!(report.total.cost_basis, 30.0);
+        assert_eq!(report.trades[0].unmatched_quantity, 0.0);
+    }
+
+    #[test]
+    fn short_sales_and_covers_are_skipped_with_a_warning() {
+        let mut transactions = history();
+        transactions.push(tx(
+            "ss1",
+            TransactionType::SellShort,
+            (2024, 5, 1),
+            5.0,
+            50.0,
+        ));
+        transactions.push(tx(
+            "bc1",
+            TransactionType::BuyToCover,
+            (2024, 6, 1),
+            5.0,
+            45.0,
+        ));
+        transactions.push(tx("s2", TransactionType::Sell, (2024, 7, 1), 15.0, 40.0));
+
+        let report = compute(&transactions, LotMethod::Fifo, &[]);
+        let sells: Vec<&str> = report
+            .trades
+            .iter()
+            .map(|t| t.sell_transaction_id.as_str())
+            .collect();
+        assert_eq!(sells, ["s1", "s2"]);
+        // The cover did not open a lot, so the long lots are used up exactly
+        assert_eq!(report.trades[1].unmatched_quantity, 0.0);
+        assert_eq!(report.total.quantity, 30.0);
+        assert_eq!(report.total.realized_pnl, 600.0);
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings[0].contains("ss1"));
+        assert!(report.warnings[1].contains("bc1"));
+    }
+
+    #[test]
+    fn written_options_are_skipped_with_a_warning() {
+        let option = |mut t: Transaction| {
+            t.symbol = Some("AAPL 240621C00200000".to_string());
+            t.security_type = Some("OPTN".to_string());
+            t
+        };
+        let transactions = vec![
+            option(tx(
+                "so1",
+                TransactionType::SellShort,
+                (2024, 1, 2),
+                1.0,
+                300.0,
+            )),
+            option(tx(
+                "bc1",
+                TransactionType::BuyToCover,
+                (2024, 2, 1),
+                1.0,
+                100.0,
+            )),
+        ];
+
+        let report = compute(&transactions, LotMethod::Fifo, &[]);
+        assert!(report.trades.is_empty());
+        assert_eq!(report.total.realized_pnl, 0.0);
+        assert_eq!(report.warnings.len(), 2);
+    }
+
+    #[test]
+    fn short_warnings_respect_the_report_range() {
+        let transactions = vec![tx(
+            "ss1",
+            TransactionType::SellShort,
+            (2023, 5, 1),
+            5.0,
+            50.0,
+        )];
+        let report = compute_realized_pnl(
+            &transactions,
+            LotMethod::Fifo,
+            PeriodGranularity::Month,
+            &[],
+            NaiveDate::from_ymd_opt(2024, 1, 1),
+            None,
+        );
+        assert!(report.warnings.is_empty());
+    }
+}
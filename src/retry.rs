@@ -0,0 +1,214 @@
+//! Retry policy for idempotent E*TRADE calls
+//!
+//! Host failures (timeouts, connection errors) and the configured status
+//! codes are retried with exponential backoff and jitter. A `Retry-After`
+//! header takes precedence over the computed delay. Only GET requests go
+//! through the policy; order placement is never retried.
+
+use crate::http::HttpResponse;
+use std::time::Duration;
+
+pub const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 3;
+pub const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+pub const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 8000;
+pub const DEFAULT_RETRY_JITTER: f64 = 0.5;
+pub const DEFAULT_RETRYABLE_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts including the first; 1 disables retries
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Fraction of the delay that is randomized (0.0 - 1.0)
+    pub jitter: f64,
+    pub retryable_statuses: Vec<u16>,
+    /// Uniform sample in `[0, 1)` used for jitter
+    pub random: fn() -> f64,
+    /// Waits out a retry delay
+    pub sleep: fn(Duration),
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_RETRY_MAX_ATTEMPTS,
+            base_delay_ms: DEFAULT_RETRY_BASE_DELAY_MS,
+            max_delay_ms: DEFAULT_RETRY_MAX_DELAY_MS,
+            jitter: DEFAULT_RETRY_JITTER,
+            retryable_statuses: DEFAULT_RETRYABLE_STATUSES.to_vec(),
+            random: rand::random::<f64>,
+            sleep: std::thread::sleep,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Read `retry_*` keys from the `initialize` config, falling back to
+    /// defaults for missing values
+    pub fn from_config(config: &serde_json::Value) -> Self {
+        let default = Self::default();
+        let get_u64 = |key: &str| config.get(key).and_then(|v| v.as_u64());
+
+        Self {
+            max_attempts: get_u64("retry_max_attempts")
+                .map(|n| n.clamp(1, u32::MAX as u64) as u32)
+                .unwrap_or(default.max_attempts),
+            base_delay_ms: get_u64("retry_base_delay_ms").unwrap_or(default.base_delay_ms),
+            max_delay_ms: get_u64("retry_max_delay_ms").unwrap_or(default.max_delay_ms),
+            jitter: config
+                .get("retry_jitter")
+                .and_then(|v| v.as_f64())
+                .map(|j| j.clamp(0.0, 1.0))
+                .unwrap_or(default.jitter),
+            retryable_statuses: config
+                .get("retry_statuses")
+                .and_then(|v| v.as_array())
+                .map(|codes| {
+                    codes
+                        .iter()
+                        .filter_map(|c| c.as_u64())
+                        .map(|c| c as u16)
+                        .collect()
+                })
+                .unwrap_or(default.retryable_statuses),
+            ..default
+        }
+    }
+
+    fn is_retryable(&self, response: &HttpResponse) -> bool {
+        response.error.is_some() || self.retryable_statuses.contains(&response.status)
+    }
+
+    /// Delay before the next attempt, or `None` if `response` is final.
+    /// `attempt` is the 1-based number of the attempt that produced it.
+    pub fn next_delay(&self, attempt: u32, response: &HttpResponse) -> Option<Duration> {
+        if attempt >= self.max_attempts || response.is_success() || !self.is_retryable(response) {
+            return None;
+        }
+
+        if let Some(secs) = response.retry_after_secs() {
+            let delay_ms = secs.saturating_mul(1000);
+            // Waiting longer than the policy allows is left to the caller
+            return (delay_ms <= self.max_delay_ms).then_some(Duration::from_millis(delay_ms));
+        }
+
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << (attempt - 1).min(20))
+            .min(self.max_delay_ms);
+        let jittered = exp as f64 * (1.0 - self.jitter * (self.random)());
+
+        Some(Duration::from_millis(jittered as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::response;
+
+    fn policy(random: fn() -> f64) -> RetryPolicy {
+        RetryPolicy {
+            random,
+            sleep: |_| {},
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn with_retry_after(secs: &str) -> HttpResponse {
+        let mut response = response(429, "application/json", "");
+        response
+            .headers
+            .insert("Retry-After".to_string(), secs.to_string());
+        response
+    }
+
+    #[test]
+    fn backs_off_exponentially_with_jitter() {
+        let unavailable = response(503, "text/html", "");
+
+        let no_jitter = policy(|| 0.0);
+        let delays: Vec<_> = (1..=2)
+            .map(|attempt| no_jitter.next_delay(attempt, &unavailable))
+            .collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some(Duration::from_millis(500)),
+                Some(Duration::from_millis(1000))
+            ]
+        );
+
+        // Half the delay is randomized by default, so a sample of 0.5
+        // takes a quarter off
+        let half = policy(|| 0.5);
+        assert_eq!(
+            half.next_delay(2, &unavailable),
+            Some(Duration::from_millis(750))
+        );
+    }
+
+    #[test]
+    fn caps_the_delay_at_max_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            ..policy(|| 0.0)
+        };
+        let unavailable = response(503, "text/html", "");
+        assert_eq!(
+            policy.next_delay(9, &unavailable),
+            Some(Duration::from_millis(DEFAULT_RETRY_MAX_DELAY_MS))
+        );
+    }
+
+    #[test]
+    fn retry_after_overrides_the_backoff() {
+        let policy = policy(|| 0.9);
+        assert_eq!(
+            policy.next_delay(1, &with_retry_after("2")),
+            Some(Duration::from_secs(2))
+        );
+        // Longer than `max_delay_ms`: not retried
+        assert_eq!(policy.next_delay(1, &with_retry_after("60")), None);
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let policy = policy(|| 0.0);
+        let unavailable = response(503, "text/html", "");
+        assert!(policy.next_delay(2, &unavailable).is_some());
+        assert_eq!(policy.next_delay(3, &unavailable), None);
+    }
+
+    #[test]
+    fn retries_host_failures_and_configured_statuses_only() {
+        let policy = policy(|| 0.0);
+        let host_timeout = HttpResponse::local_error("Request timed out".to_string());
+
+        assert!(policy.next_delay(1, &host_timeout).is_some());
+        assert!(policy
+            .next_delay(1, &response(429, "application/json", ""))
+            .is_some());
+        assert_eq!(
+            policy.next_delay(1, &response(400, "application/json", "")),
+            None
+        );
+        assert_eq!(
+            policy.next_delay(1, &response(200, "application/json", "{}")),
+            None
+        );
+    }
+
+    #[test]
+    fn config_replaces_retryable_statuses() {
+        let policy = RetryPolicy::from_config(&serde_json::json!({
+            "retry_statuses": [400],
+            "retry_jitter": 0.0
+        }));
+        assert!(policy
+            .next_delay(1, &response(400, "application/json", ""))
+            .is_some());
+        assert_eq!(policy.next_delay(1, &response(503, "text/html", "")), None);
+    }
+}
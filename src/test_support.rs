@@ -0,0 +1,143 @@
+//! Native test harness for the export handlers
+//!
+//! Tests call the handlers directly with request bytes; the pointer shims
+//! are not exercised. Under `cfg(test)`, `http::execute` is this module's
+//! [`execute`]. It answers from a per-thread responder (canned E*TRADE
+//! fixtures by default) and records every request it is sent. A counting
+//! allocator tracks live bytes per thread, so leaks show up in soak tests.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::http::{HttpRequest, HttpResponse};
+
+type Responder = Box<dyn FnMut(&HttpRequest) -> HttpResponse>;
+
+thread_local! {
+    static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    static RESPONDER: RefCell<Option<Responder>> = const { RefCell::new(None) };
+    static SENT: RefCell<Vec<HttpRequest>> = const { RefCell::new(Vec::new()) };
+}
+
+/// System allocator that counts the bytes each thread has live
+struct CountingAllocator;
+
+fn track(delta: isize) {
+    // The counter may already be gone while a thread shuts down
+    let _ = LIVE_BYTES.try_with(|live| live.set(live.get() + delta));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            track(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        track(-(layout.size() as isize));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Bytes allocated and not yet freed by the current thread
+pub fn live_bytes() -> isize {
+    LIVE_BYTES.with(|live| live.get())
+}
+
+/// Canned E*TRADE body for a request URL
+fn fixture(url: &str) -> serde_json::Value {
+    if url.contains("/v1/accounts/list") {
+        serde_json::json!({"AccountListResponse": {"Accounts": {"Account": [{
+            "accountId": "12345678",
+            "accountIdKey": "dBZOKt9xDrtRSAOl4MSiiA",
+            "accountType": "INDIVIDUAL",
+            "accountMode": "CASH",
+            "accountStatus": "ACTIVE",
+            "institutionType": "BROKERAGE"
+        }]}}})
+    } else if url.contains("/balance") {
+        serde_json::json!({"BalanceResponse": {
+            "accountMode": "CASH",
+            "Computed": {"cashAvailableForInvestment": 1000.0, "netCash": 1000.0}
+        }})
+    } else if url.contains("/portfolio") {
+        serde_json::json!({"PortfolioResponse": {"AccountPortfolio": [{
+            "accountId": "12345678",
+            "totalPages": 1,
+            "Position": [{
+                "positionId": 1,
+                "Product": {"symbol": "AAPL", "securityType": "EQ"},
+                "positionType": "LONG",
+                "quantity": 10,
+                "costPerShare": 100.0,
+                "marketValue": 1500.0,
+                "Quick": {"lastTrade": 150.0}
+            }]
+        }]}})
+    } else if url.contains("/v1/market/quote") {
+        serde_json::json!({"QuoteResponse": {"QuoteData": [{
+            "Product": {"symbol": "AAPL"},
+            "dateTimeUTC": 1700000000,
+            "All": {"lastTrade": 150.0}
+        }]}})
+    } else {
+        serde_json::json!({})
+    }
+}
+
+/// Response with the given status, content type and body
+pub fn response(status: u16, content_type: &str, body: &str) -> HttpResponse {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type".to_string(), content_type.to_string());
+    HttpResponse {
+        status,
+        headers,
+        body: body.to_string(),
+        error: None,
+    }
+}
+
+/// 200 with the fixture for the request URL
+pub fn fixture_response(request: &HttpRequest) -> HttpResponse {
+    response(200, "application/json", &fixture(&request.url).to_string())
+}
+
+/// Answer this thread's requests with `responder` instead of the fixtures
+pub fn respond_with<F: FnMut(&HttpRequest) -> HttpResponse + 'static>(responder: F) {
+    RESPONDER.with(|r| *r.borrow_mut() = Some(Box::new(responder)));
+}
+
+/// Requests sent on this thread since the last call
+pub fn take_sent() -> Vec<HttpRequest> {
+    SENT.with(|sent| std::mem::take(&mut *sent.borrow_mut()))
+}
+
+/// Stand-in for the host `http_request` function
+pub fn execute(request: HttpRequest) -> HttpResponse {
+    let response = RESPONDER.with(|r| match r.borrow_mut().as_mut() {
+        Some(responder) => responder(&request),
+        None => fixture_response(&request),
+    });
+    SENT.with(|sent| sent.borrow_mut().push(request));
+    response
+}
+
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Serialize tests that read or replace the global plugin state
+pub fn lock_state() -> MutexGuard<'static, ()> {
+    STATE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Call a handler and decode its response
+pub fn call(handler: fn(&[u8]) -> Vec<u8>, request: &[u8]) -> serde_json::Value {
+    serde_json::from_slice(&handler(request)).expect("handler returned invalid JSON")
+}
@@ -0,0 +1,126 @@
+//! Per-operation request timeouts
+//!
+//! Each request's `timeout_ms` is chosen by the kind of operation its path
+//! belongs to. `timeout_ms` in the `initialize` config sets all of them;
+//! `timeout_{auth,account,market,order}_ms` override individual operations.
+
+use serde::Serialize;
+
+pub const DEFAULT_TIMEOUT_MS: u32 = 30000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Auth,
+    Account,
+    Market,
+    Order,
+}
+
+impl Operation {
+    /// Classify a request path or absolute URL. The rate limit family is
+    /// derived from this as well.
+    pub fn from_path(path: &str) -> Self {
+        if path.contains("/oauth/") {
+            Operation::Auth
+        } else if path.contains("/v1/market/") {
+            Operation::Market
+        } else if path.contains("/orders") {
+            Operation::Order
+        } else {
+            Operation::Account
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub auth_ms: u32,
+    pub account_ms: u32,
+    pub market_ms: u32,
+    pub order_ms: u32,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            auth_ms: DEFAULT_TIMEOUT_MS,
+            account_ms: DEFAULT_TIMEOUT_MS,
+            market_ms: DEFAULT_TIMEOUT_MS,
+            order_ms: DEFAULT_TIMEOUT_MS,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn from_config(config: &serde_json::Value) -> Self {
+        let get_u32 = |key: &str| {
+            config
+                .get(key)
+                .and_then(|v| v.as_u64())
+                .map(|n| n.clamp(1, u32::MAX as u64) as u32)
+        };
+        let base = get_u32("timeout_ms").unwrap_or(DEFAULT_TIMEOUT_MS);
+
+        Self {
+            auth_ms: get_u32("timeout_auth_ms").unwrap_or(base),
+            account_ms: get_u32("timeout_account_ms").unwrap_or(base),
+            market_ms: get_u32("timeout_market_ms").unwrap_or(base),
+            order_ms: get_u32("timeout_order_ms").unwrap_or(base),
+        }
+    }
+
+    pub fn for_operation(&self, operation: Operation) -> u32 {
+        match operation {
+            Operation::Auth => self.auth_ms,
+            Operation::Account => self.account_ms,
+            Operation::Market => self.market_ms,
+            Operation::Order => self.order_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limit::EndpointFamily;
+
+    #[test]
+    fn classifies_paths_and_urls() {
+        let cases = [
+            ("/oauth/renew_access_token", Operation::Auth),
+            ("/v1/market/quote/AAPL", Operation::Market),
+            ("/v1/accounts/abc/orders/place", Operation::Order),
+            ("/v1/accounts/abc/portfolio", Operation::Account),
+            (
+                "https://api.etrade.com/v1/market/lookup/apple",
+                Operation::Market,
+            ),
+        ];
+        for (path, operation) in cases {
+            assert_eq!(Operation::from_path(path), operation, "{}", path);
+        }
+    }
+
+    #[test]
+    fn rate_limit_families_follow_the_operation() {
+        let family = |path| EndpointFamily::from(Operation::from_path(path));
+        assert_eq!(
+            family("/oauth/renew_access_token"),
+            EndpointFamily::Accounts
+        );
+        assert_eq!(family("/v1/accounts/list"), EndpointFamily::Accounts);
+        assert_eq!(family("/v1/accounts/abc/orders"), EndpointFamily::Orders);
+        assert_eq!(family("/v1/market/quote/AAPL"), EndpointFamily::Market);
+    }
+
+    #[test]
+    fn per_operation_timeouts_override_the_base() {
+        let timeouts = Timeouts::from_config(&serde_json::json!({
+            "timeout_ms": 10000,
+            "timeout_order_ms": 60000
+        }));
+        assert_eq!(timeouts.for_operation(Operation::Account), 10000);
+        assert_eq!(timeouts.for_operation(Operation::Order), 60000);
+    }
+}
@@ -0,0 +1,381 @@
+//! Request/response tracing
+//!
+//! Every E*TRADE call is recorded in an in-memory ring buffer and, depending
+//! on `log_level`, printed to stderr; the level never affects the buffer.
+//! All other plugin diagnostics go through `log`, which applies the same
+//! level and redaction. Entries never contain secrets: the account segment
+//! of paths, token-like query values, `Authorization` and cookie headers
+//! are masked, and long digit runs (account numbers) in error messages keep
+//! only their last four digits.
+
+use crate::error::PluginError;
+use crate::http::{HttpMethod, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+pub const DEFAULT_TRACE_BUFFER_SIZE: usize = 200;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Response headers that may carry a request ID
+const REQUEST_ID_HEADERS: [&str; 3] = ["X-Request-Id", "X-Correlation-Id", "apigw-requestid"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    /// Transport failures and 5xx responses
+    Error,
+    /// Plus 4xx responses
+    Warn,
+    /// Every call
+    Info,
+    /// Every call with redacted response headers
+    Debug,
+}
+
+impl LogLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Off,
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+
+    /// Level at which a failure is reported: transport and server-side
+    /// failures are errors, rejections of the request are warnings
+    pub fn for_error(error: &PluginError) -> Self {
+        match error {
+            PluginError::Network { .. }
+            | PluginError::Timeout { .. }
+            | PluginError::CircuitOpen { .. }
+            | PluginError::Serialization { .. } => LogLevel::Error,
+            _ => LogLevel::Warn,
+        }
+    }
+}
+
+/// Process-wide level for stderr output, set by `initialize`
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
+
+pub fn set_log_level(level: LogLevel) {
+    LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn log_level() -> LogLevel {
+    LogLevel::from_u8(LOG_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Print a redacted diagnostic line to stderr if `level` is enabled
+pub fn log(level: LogLevel, message: &str) {
+    if level != LogLevel::Off && level <= log_level() {
+        eprintln!("[broker-etrade] {}", redact_text(message));
+    }
+}
+
+/// Log a failed operation at the level its error calls for
+pub fn log_error(context: &str, error: &PluginError) {
+    log(
+        LogLevel::for_error(error),
+        &format!("{}: {}", context, error),
+    );
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEntry {
+    pub timestamp: DateTime<Utc>,
+    pub method: &'static str,
+    pub path: String,
+    pub attempt: u32,
+    pub status: u16,
+    pub latency_ms: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
+}
+
+pub struct Tracer {
+    level: LogLevel,
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self::from_config(&serde_json::Value::Null)
+    }
+}
+
+impl Tracer {
+    /// Read `log_level` and `trace_buffer_size` from the `initialize` config
+    pub fn from_config(config: &serde_json::Value) -> Self {
+        Self {
+            level: config
+                .get("log_level")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or(LogLevel::Warn),
+            capacity: config
+                .get("trace_buffer_size")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize)
+                .unwrap_or(DEFAULT_TRACE_BUFFER_SIZE),
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    /// Record one attempt of a call in the buffer, and print it if its
+    /// severity is within `log_level`. `error` is the caller's
+    /// classification of a failed response.
+    pub fn record(
+        &mut self,
+        method: &HttpMethod,
+        path: &str,
+        attempt: u32,
+        response: &HttpResponse,
+        error: Option<&PluginError>,
+        latency_ms: i64,
+    ) {
+        let entry_level = match error {
+            None => LogLevel::Info,
+            Some(e) => LogLevel::for_error(e),
+        };
+
+        let entry = TraceEntry {
+            timestamp: Utc::now(),
+            method: method.as_str(),
+            path: redact_path(path),
+            attempt,
+            status: response.status,
+            latency_ms,
+            error_code: match error {
+                Some(PluginError::BrokerRejected { code, .. }) => *code,
+                _ => None,
+            },
+            error: error.map(|e| redact_text(&e.to_string())),
+            request_id: REQUEST_ID_HEADERS
+                .iter()
+                .find_map(|h| response.header(h))
+                .map(|id| id.to_string()),
+            headers: (self.level == LogLevel::Debug).then(|| redact_headers(&response.headers)),
+        };
+
+        if entry_level <= self.level {
+            log(
+                entry_level,
+                &format!(
+                    "{} {} -> {} in {}ms (attempt {}){}",
+                    entry.method,
+                    entry.path,
+                    entry.status,
+                    entry.latency_ms,
+                    entry.attempt,
+                    entry
+                        .error
+                        .as_ref()
+                        .map(|e| format!(": {}", e))
+                        .unwrap_or_default()
+                ),
+            );
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Most recent `limit` entries, oldest first
+    pub fn recent(&self, limit: usize) -> Vec<TraceEntry> {
+        let skip = self.entries.len().saturating_sub(limit);
+        self.entries.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Path without scheme and host, with the account segment of
+/// `/v1/accounts/{accountIdKey}/...` and token-like query values masked
+pub fn redact_path(path: &str) -> String {
+    let path = match path.find("://") {
+        Some(i) => {
+            let rest = &path[i + 3..];
+            rest.find('/').map(|j| &rest[j..]).unwrap_or("/")
+        }
+        None => path,
+    };
+    let (path, query) = match path.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (path, None),
+    };
+
+    let mut after_accounts = false;
+    let segments: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            let masked = after_accounts && !segment.is_empty() && segment != "list";
+            after_accounts = segment == "accounts";
+            if masked {
+                "{account}"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    let mut redacted = segments.join("/");
+
+    if let Some(query) = query {
+        let params: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((k, _)) if is_secret_name(k) => format!("{}={}", k, REDACTED),
+                _ => pair.to_string(),
+            })
+            .collect();
+        redacted.push('?');
+        redacted.push_str(&params.join("&"));
+    }
+
+    redacted
+}
+
+/// Mask digit runs of six or more (account numbers) except the last four
+/// digits, and values of `oauth_token`-style fields
+pub fn redact_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut digits = String::new();
+    let flush = |digits: &mut String, out: &mut String| {
+        if digits.len() >= 6 {
+            out.push_str("****");
+            out.push_str(&digits[digits.len() - 4..]);
+        } else {
+            out.push_str(digits);
+        }
+        digits.clear();
+    };
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else {
+            flush(&mut digits, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut digits, &mut out);
+
+    out.split('&')
+        .map(|part| match part.split_once('=') {
+            Some((k, _)) if is_secret_name(k.rsplit(' ').next().unwrap_or(k)) => {
+                format!("{}={}", k, REDACTED)
+            }
+            _ => part.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn redact_headers(headers: &HashMap<String, String>) -> HashMap<String, String> {
+    headers
+        .iter()
+        .map(|(k, v)| {
+            let lower = k.to_ascii_lowercase();
+            let value = if lower == "authorization" || lower.contains("cookie") || is_secret_name(k)
+            {
+                REDACTED.to_string()
+            } else {
+                v.clone()
+            };
+            (k.clone(), value)
+        })
+        .collect()
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let lower = name.to_ascii_lowercase();
+    lower.contains("token") || lower.contains("secret") || lower == "oauth_signature"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok() -> HttpResponse {
+        crate::test_support::response(200, "application/json", "{}")
+    }
+
+    #[test]
+    fn records_calls_when_logging_is_off() {
+        let mut tracer = Tracer::from_config(&serde_json::json!({
+            "log_level": "off",
+            "trace_buffer_size": 2
+        }));
+        for attempt in 1..=3 {
+            tracer.record(
+                &HttpMethod::Get,
+                "/v1/accounts/list",
+                attempt,
+                &ok(),
+                None,
+                5,
+            );
+        }
+
+        let attempts: Vec<u32> = tracer.recent(10).iter().map(|e| e.attempt).collect();
+        assert_eq!(attempts, vec![2, 3]);
+    }
+
+    #[test]
+    fn log_level_round_trips() {
+        for level in [
+            LogLevel::Off,
+            LogLevel::Error,
+            LogLevel::Warn,
+            LogLevel::Info,
+            LogLevel::Debug,
+        ] {
+            assert_eq!(LogLevel::from_u8(level as u8), level);
+        }
+    }
+
+    #[test]
+    fn failures_are_logged_by_severity() {
+        let network = PluginError::Network {
+            status: Some(503),
+            message: "unavailable".to_string(),
+        };
+        assert_eq!(LogLevel::for_error(&network), LogLevel::Error);
+        let rejected = PluginError::validation("bad symbol");
+        assert_eq!(LogLevel::for_error(&rejected), LogLevel::Warn);
+    }
+
+    #[test]
+    fn redacts_account_numbers_and_tokens() {
+        assert_eq!(
+            redact_text("Account 12345678 positions unavailable"),
+            "Account ****5678 positions unavailable"
+        );
+        assert_eq!(
+            redact_text("oauth_problem=token_rejected&oauth_token=abc"),
+            "oauth_problem=token_rejected&oauth_token=[REDACTED]"
+        );
+        assert_eq!(
+            redact_path("https://api.etrade.com/v1/accounts/AbC123/portfolio?count=50"),
+            "/v1/accounts/{account}/portfolio?count=50"
+        );
+    }
+}
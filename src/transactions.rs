@@ -0,0 +1,409 @@
+//! Transaction history model
+//!
+//! Typed view over `/v1/accounts/{id}/transactions`. E*TRADE reports the
+//! transaction type as free text ("Bought", "Qualified Dividend", ...), so it
+//! is classified into `TransactionType` here.
+
+use crate::common::{millis_to_utc, value_to_string};
+use crate::error::PluginError;
+use crate::oauth::percent_encode;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Maximum transactions E*TRADE returns per page
+pub const MAX_TRANSACTION_PAGE: u32 = 50;
+
+/// Pages `collect_pages` follows before giving up (25,000 transactions)
+pub const MAX_TRANSACTION_PAGES: u32 = 500;
+
+/// How far back E*TRADE serves transaction history (two years)
+pub const MAX_HISTORY_DAYS: i64 = 730;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionType {
+    Buy,
+    Sell,
+    /// Opens a short position: short stock sale or written option
+    SellShort,
+    /// Closes a short position: buy to cover or option bought to close
+    BuyToCover,
+    Dividend,
+    QualifiedDividend,
+    Reinvestment,
+    Interest,
+    Withholding,
+    Fee,
+    Transfer,
+    Journal,
+    Other,
+}
+
+impl TransactionType {
+    /// Classify E*TRADE's free-text `transactionType`
+    pub fn from_etrade(raw: &str) -> Self {
+        let t = raw.to_lowercase();
+
+        if t.contains("reinvest") {
+            TransactionType::Reinvestment
+        } else if is_withholding(&t) {
+            TransactionType::Withholding
+        } else if t.contains("qualified dividend") && !t.contains("non-qualified") {
+            TransactionType::QualifiedDividend
+        } else if t.contains("dividend") {
+            TransactionType::Dividend
+        } else if t.contains("interest") {
+            TransactionType::Interest
+        } else if t.contains("sold short") || t.contains("sold to open") {
+            TransactionType::SellShort
+        } else if t.contains("to cover") || t.contains("bought to close") {
+            TransactionType::BuyToCover
+        } else if t.starts_with("bought") || t.starts_with("buy") {
+            TransactionType::Buy
+        } else if t.starts_with("sold") || t.starts_with("sell") {
+            TransactionType::Sell
+        } else if t.contains("fee") || t.contains("commission") {
+            TransactionType::Fee
+        } else if t.contains("journal") {
+            TransactionType::Journal
+        } else if t.contains("transfer")
+            || t.contains("wire")
+            || t.contains("deposit")
+            || t.contains("withdrawal")
+            || t.contains("contribution")
+            || t.starts_with("ach")
+        {
+            TransactionType::Transfer
+        } else {
+            TransactionType::Other
+        }
+    }
+}
+
+/// Tax withheld from income. Checked before dividend/interest because
+/// E*TRADE reports it as e.g. "Dividend - Foreign Tax Withheld", but plain
+/// "tax" would also catch "Tax-Exempt Interest".
+fn is_withholding(t: &str) -> bool {
+    const PHRASES: &[&str] = &[
+        "withholding",
+        "withheld",
+        "foreign tax",
+        "nra tax",
+        "tax w/h",
+    ];
+    PHRASES.iter().any(|p| t.contains(p))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Query parameters for a single transactions page
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransactionQuery {
+    #[serde(default)]
+    pub start_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub end_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub sort_order: SortOrder,
+    #[serde(default)]
+    pub marker: Option<String>,
+    #[serde(default)]
+    pub count: Option<u32>,
+}
+
+impl TransactionQuery {
+    /// Build the query string (E*TRADE expects dates as MMDDYYYY)
+    pub fn to_query_string(&self) -> String {
+        let mut params = vec![format!("sortOrder={}", self.sort_order.as_str())];
+
+        if let Some(d) = self.start_date {
+            params.push(format!("startDate={}", d.format("%m%d%Y")));
+        }
+        if let Some(d) = self.end_date {
+            params.push(format!("endDate={}", d.format("%m%d%Y")));
+        }
+        if let Some(m) = &self.marker {
+            params.push(format!("marker={}", percent_encode(m)));
+        }
+
+        let count = self
+            .count
+            .unwrap_or(MAX_TRANSACTION_PAGE)
+            .clamp(1, MAX_TRANSACTION_PAGE);
+        params.push(format!("count={}", count));
+
+        params.join("&")
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transaction {
+    pub id: String,
+    pub account_id: String,
+    pub transaction_type: TransactionType,
+    /// Transaction type exactly as reported by E*TRADE
+    pub raw_type: String,
+    pub transaction_date: Option<DateTime<Utc>>,
+    pub post_date: Option<DateTime<Utc>>,
+    pub amount: f64,
+    pub description: String,
+    pub symbol: Option<String>,
+    pub security_type: Option<String>,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+    pub fee: Option<f64>,
+    pub memo: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    /// Marker to pass back for the next page, if any
+    pub next_marker: Option<String>,
+    pub more: bool,
+    pub total_count: Option<i64>,
+}
+
+/// Fetch every page of `query`, following markers until E*TRADE reports
+/// no more. A server that keeps handing out new markers stops with a
+/// `truncated` error after `MAX_TRANSACTION_PAGES` instead of looping.
+pub fn collect_pages<F>(
+    query: &TransactionQuery,
+    mut fetch: F,
+) -> Result<Vec<Transaction>, PluginError>
+where
+    F: FnMut(&TransactionQuery) -> Result<TransactionPage, PluginError>,
+{
+    let mut query = query.clone();
+    let mut transactions = Vec::new();
+
+    for _ in 0..MAX_TRANSACTION_PAGES {
+        let page = fetch(&query)?;
+        transactions.extend(page.transactions);
+
+        match page.next_marker {
+            Some(marker) if page.more && query.marker.as_ref() != Some(&marker) => {
+                query.marker = Some(marker);
+            }
+            _ => return Ok(transactions),
+        }
+    }
+
+    Err(PluginError::truncated(format!(
+        "Transaction history has more than {} pages of {} transactions",
+        MAX_TRANSACTION_PAGES, MAX_TRANSACTION_PAGE
+    )))
+}
+
+// --- E*TRADE wire format ---
+
+#[derive(Deserialize)]
+pub(crate) struct ETradeTransaction {
+    #[serde(rename = "transactionId")]
+    pub transaction_id: serde_json::Value,
+    #[serde(rename = "accountId", default)]
+    pub account_id: Option<serde_json::Value>,
+    #[serde(rename = "transactionDate")]
+    pub transaction_date: Option<i64>,
+    #[serde(rename = "postDate")]
+    pub post_date: Option<i64>,
+    pub amount: Option<f64>,
+    pub description: Option<String>,
+    #[serde(rename = "transactionType")]
+    pub transaction_type: Option<String>,
+    pub memo: Option<String>,
+    pub brokerage: Option<ETradeBrokerage>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ETradeBrokerage {
+    #[serde(rename = "transactionType")]
+    pub transaction_type: Option<String>,
+    pub product: Option<ETradeTxProduct>,
+    pub quantity: Option<f64>,
+    pub price: Option<f64>,
+    pub fee: Option<f64>,
+    #[serde(rename = "displaySymbol")]
+    pub display_symbol: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ETradeTxProduct {
+    pub symbol: Option<String>,
+    #[serde(rename = "securityType")]
+    pub security_type: Option<String>,
+}
+
+impl From<ETradeTransaction> for Transaction {
+    fn from(tx: ETradeTransaction) -> Self {
+        let brokerage = tx.brokerage;
+        let raw_type = tx
+            .transaction_type
+            .or_else(|| brokerage.as_ref().and_then(|b| b.transaction_type.clone()))
+            .unwrap_or_default();
+        let product = brokerage.as_ref().and_then(|b| b.product.as_ref());
+
+        let symbol = product
+            .and_then(|p| p.symbol.clone())
+            .or_else(|| brokerage.as_ref().and_then(|b| b.display_symbol.clone()))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+
+        Transaction {
+            id: value_to_string(&tx.transaction_id),
+            account_id: tx
+                .account_id
+                .as_ref()
+                .map(value_to_string)
+                .unwrap_or_default(),
+            transaction_type: TransactionType::from_etrade(&raw_type),
+            raw_type,
+            transaction_date: tx.transaction_date.and_then(millis_to_utc),
+            post_date: tx.post_date.and_then(millis_to_utc),
+            amount: tx.amount.unwrap_or(0.0),
+            description: tx.description.unwrap_or_default(),
+            symbol,
+            security_type: product.and_then(|p| p.security_type.clone()),
+            quantity: brokerage.as_ref().and_then(|b| b.quantity),
+            price: brokerage.as_ref().and_then(|b| b.price),
+            fee: brokerage.as_ref().and_then(|b| b.fee),
+            memo: tx.memo,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(raw: &str) -> TransactionType {
+        TransactionType::from_etrade(raw)
+    }
+
+    #[test]
+    fn classifies_withholding_phrases() {
+        assert_eq!(
+            classify("Foreign Tax Withheld"),
+            TransactionType::Withholding
+        );
+        assert_eq!(
+            classify("Dividend - Foreign Tax Withheld"),
+            TransactionType::Withholding
+        );
+        assert_eq!(classify("Backup Withholding"), TransactionType::Withholding);
+        assert_eq!(classify("NRA Tax Adj"), TransactionType::Withholding);
+    }
+
+    #[test]
+    fn tax_in_income_types_is_not_withholding() {
+        assert_eq!(classify("Tax-Exempt Interest"), TransactionType::Interest);
+        assert_eq!(classify("Tax Free Dividend"), TransactionType::Dividend);
+        assert_eq!(classify("Tax Exempt Dividend"), TransactionType::Dividend);
+    }
+
+    #[test]
+    fn classifies_income() {
+        assert_eq!(
+            classify("Qualified Dividend"),
+            TransactionType::QualifiedDividend
+        );
+        assert_eq!(
+            classify("Non-Qualified Dividend"),
+            TransactionType::Dividend
+        );
+        assert_eq!(classify("Dividend"), TransactionType::Dividend);
+        assert_eq!(classify("Interest Income"), TransactionType::Interest);
+        assert_eq!(
+            classify("Dividend Reinvestment"),
+            TransactionType::Reinvestment
+        );
+    }
+
+    #[test]
+    fn classifies_trades_and_cash_movements() {
+        assert_eq!(classify("Bought"), TransactionType::Buy);
+        assert_eq!(classify("Sold"), TransactionType::Sell);
+        assert_eq!(classify("Sold Short"), TransactionType::SellShort);
+        assert_eq!(classify("Bought To Cover"), TransactionType::BuyToCover);
+        assert_eq!(classify("Sold To Open"), TransactionType::SellShort);
+        assert_eq!(classify("Bought To Close"), TransactionType::BuyToCover);
+        assert_eq!(classify("Bought To Open"), TransactionType::Buy);
+        assert_eq!(classify("Sold To Close"), TransactionType::Sell);
+        assert_eq!(classify("Service Fee"), TransactionType::Fee);
+        assert_eq!(classify("Journal"), TransactionType::Journal);
+        assert_eq!(classify("Online Transfer"), TransactionType::Transfer);
+        assert_eq!(classify("ACH Deposit"), TransactionType::Transfer);
+        assert_eq!(classify("Stock Split"), TransactionType::Other);
+    }
+
+    fn page(id: &str, marker: Option<String>) -> TransactionPage {
+        let tx: ETradeTransaction = serde_json::from_value(serde_json::json!({
+            "transactionId": id,
+            "transactionType": "Bought"
+        }))
+        .unwrap();
+        TransactionPage {
+            transactions: vec![tx.into()],
+            more: marker.is_some(),
+            next_marker: marker,
+            total_count: None,
+        }
+    }
+
+    #[test]
+    fn collect_pages_follows_markers_until_the_last_page() {
+        let mut markers = Vec::new();
+        let transactions = collect_pages(&TransactionQuery::default(), |q| {
+            markers.push(q.marker.clone());
+            Ok(match q.marker.as_deref() {
+                None => page("1", Some("m1".to_string())),
+                Some("m1") => page("2", Some("m2".to_string())),
+                _ => page("3", None),
+            })
+        })
+        .unwrap();
+
+        let ids: Vec<&str> = transactions.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+        assert_eq!(
+            markers,
+            [None, Some("m1".to_string()), Some("m2".to_string())]
+        );
+    }
+
+    #[test]
+    fn collect_pages_stops_on_a_repeated_marker() {
+        let transactions = collect_pages(&TransactionQuery::default(), |_| {
+            Ok(page("1", Some("same".to_string())))
+        })
+        .unwrap();
+        assert_eq!(transactions.len(), 2);
+    }
+
+    #[test]
+    fn collect_pages_gives_up_on_endless_markers() {
+        let mut fetched = 0;
+        let result = collect_pages(&TransactionQuery::default(), |_| {
+            fetched += 1;
+            Ok(page("1", Some(format!("m{}", fetched))))
+        });
+
+        assert!(matches!(result, Err(PluginError::Truncated { .. })));
+        assert_eq!(fetched, MAX_TRANSACTION_PAGES);
+    }
+}
@@ -0,0 +1,101 @@
+//! Minimal XML reading
+//!
+//! E*TRADE answers in XML when it ignores `Accept: application/json`
+//! (mostly error pages). Only leaf element text is needed to classify such
+//! responses, so this avoids pulling in a full XML parser.
+
+/// Raw content between `<tag ...>` and `</tag>` of the first `tag` element.
+/// An element nested inside another of the same name is not supported.
+pub fn element_inner<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+
+    let mut from = 0;
+    while let Some(pos) = xml[from..].find(&open) {
+        let start = from + pos + open.len();
+        // Skip longer tag names sharing the prefix (e.g. `<codeType>`)
+        match xml[start..].chars().next() {
+            Some('>') | Some(' ') | Some('\t') | Some('\n') | Some('\r') => {}
+            Some('/') => return Some(""),
+            _ => {
+                from = start;
+                continue;
+            }
+        }
+        let content_start = start + xml[start..].find('>')? + 1;
+        if xml[start..content_start].ends_with("/>") {
+            return Some("");
+        }
+        let content_end = content_start + xml[content_start..].find(&close)?;
+        return Some(&xml[content_start..content_end]);
+    }
+    None
+}
+
+/// Unescaped text of the first `tag` element
+pub fn element_text(xml: &str, tag: &str) -> Option<String> {
+    let inner = element_inner(xml, tag)?.trim();
+    let inner = inner
+        .strip_prefix("<![CDATA[")
+        .and_then(|s| s.strip_suffix("]]>"))
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| unescape(inner));
+    Some(inner)
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ERROR: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error>
+    <codeType>internal</codeType>
+    <code>1002</code>
+    <message lang="en">Symbol &lt;XYZ&gt; is invalid</message>
+    <detail/>
+    <hint />
+    <raw><![CDATA[a < b & c]]></raw>
+</Error>"#;
+
+    #[test]
+    fn reads_children_of_nested_elements() {
+        let error = element_inner(ERROR, "Error").unwrap();
+        assert!(error.trim_start().starts_with("<codeType>"));
+        assert_eq!(element_text(error, "code").as_deref(), Some("1002"));
+        assert_eq!(element_text(error, "codeType").as_deref(), Some("internal"));
+    }
+
+    #[test]
+    fn skips_attributes_on_the_tag() {
+        assert_eq!(
+            element_text(ERROR, "message").as_deref(),
+            Some("Symbol <XYZ> is invalid")
+        );
+    }
+
+    #[test]
+    fn self_closing_elements_are_empty() {
+        assert_eq!(element_inner(ERROR, "detail"), Some(""));
+        assert_eq!(element_inner(ERROR, "hint"), Some(""));
+    }
+
+    #[test]
+    fn keeps_cdata_verbatim() {
+        assert_eq!(element_text(ERROR, "raw").as_deref(), Some("a < b & c"));
+    }
+
+    #[test]
+    fn missing_elements_and_closing_tags_yield_none() {
+        assert_eq!(element_inner(ERROR, "status"), None);
+        assert_eq!(element_inner("<Error><code>1002</Error>", "code"), None);
+        assert_eq!(element_inner("<code", "code"), None);
+    }
+}